@@ -0,0 +1,34 @@
+use aoc2025::day3::{max_joltage, max_joltage_rescan};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+
+// A bank of `len` pseudo-random battery digits.
+fn generate_bank(len: usize, mut seed: u64) -> String {
+    (0..len)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            char::from(b'1' + (seed % 9) as u8)
+        })
+        .collect()
+}
+
+fn bench_max_joltage(c: &mut Criterion) {
+    let mut group = c.benchmark_group("day3_max_joltage");
+    for len in [1_000, 5_000, 20_000] {
+        let bank = generate_bank(len, 0x9e37_79b9_7f4a_7c15);
+        for num_batteries in [12, 19] {
+            let id = format!("{len}/{num_batteries}");
+            group.bench_with_input(BenchmarkId::new("stack", &id), &bank, |b, bank| {
+                b.iter(|| max_joltage(bank, num_batteries))
+            });
+            group.bench_with_input(BenchmarkId::new("rescan", &id), &bank, |b, bank| {
+                b.iter(|| max_joltage_rescan(bank, num_batteries))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_max_joltage);
+criterion_main!(benches);
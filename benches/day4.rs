@@ -0,0 +1,59 @@
+use aoc2025::day4::{BitGrid, Rules, count_accessible, count_removable, count_removable_rescan};
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+
+// A `size` x `size` grid with roughly 70% of the cells holding rolls.
+fn generate_grid(size: usize, mut seed: u64) -> String {
+    (0..size)
+        .map(|_| {
+            (0..size)
+                .map(|_| {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    if seed % 10 < 7 { '@' } else { '.' }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn bench_grids(c: &mut Criterion) {
+    let mut group = c.benchmark_group("day4");
+    group.sample_size(10);
+    for size in [500, 2_000, 5_000] {
+        let grid = generate_grid(size, 0x9e37_79b9_7f4a_7c15);
+        group.bench_with_input(
+            BenchmarkId::new("accessible/bits", size),
+            &grid,
+            |b, grid| b.iter(|| BitGrid::new(grid).count_accessible()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("accessible/rules", size),
+            &grid,
+            |b, grid| b.iter(|| count_accessible(grid, &Rules::default())),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("removable/bits", size),
+            &grid,
+            |b, grid| b.iter(|| BitGrid::new(grid).remove_all_accessible()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("removable/worklist", size),
+            &grid,
+            |b, grid| b.iter(|| count_removable(grid, &Rules::default())),
+        );
+        // Rescanning takes tens of seconds per run on the largest grid.
+        if size <= 2_000 {
+            group.bench_with_input(
+                BenchmarkId::new("removable/rescan", size),
+                &grid,
+                |b, grid| b.iter(|| count_removable_rescan(grid)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_grids);
+criterion_main!(benches);
@@ -1,66 +1,565 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 
-use std::ops::RangeInclusive;
+use std::ops::{AddAssign, Range, RangeInclusive, Sub};
+
+use crate::ranges;
+
+/// The base that IDs are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Radix(u32);
+impl Radix {
+    pub const DECIMAL: Radix = Radix(10);
+
+    pub fn new(radix: u32) -> Self {
+        assert!((2..=36).contains(&radix), "radix {radix} not in 2..=36");
+        Radix(radix)
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+
+    fn pow(self, exp: u32) -> u128 {
+        (self.0 as u128).pow(exp)
+    }
+
+    fn num_digits(self, x: usize) -> u32 {
+        std::cmp::max(x, 1).ilog(self.0 as usize) + 1
+    }
+
+    // Radix::DECIMAL.digits(1234) -> [1, 2, 3, 4]
+    fn digits(self, mut x: usize) -> Vec<u8> {
+        let radix = self.0 as usize;
+        let mut digits = Vec::with_capacity(self.num_digits(x) as usize);
+        loop {
+            digits.push((x % radix) as u8);
+            x /= radix;
+            if x == 0 {
+                break;
+            }
+        }
+        digits.reverse();
+        digits
+    }
+
+    // The smallest value with `num_digits` digits, where 0 has one digit.
+    fn min_value(self, num_digits: u32) -> u128 {
+        if num_digits == 1 {
+            0
+        } else {
+            self.pow(num_digits - 1)
+        }
+    }
+
+    // The part of `range` whose values have exactly `num_digits` digits.
+    fn digits_band(self, range: &RangeInclusive<usize>, num_digits: u32) -> RangeInclusive<u128> {
+        let start = std::cmp::max(*range.start() as u128, self.min_value(num_digits));
+        let end = std::cmp::min(*range.end() as u128, self.pow(num_digits) - 1);
+        start..=end
+    }
+
+    // The digit lengths of the values in `range`.
+    fn digit_lengths(self, range: &RangeInclusive<usize>) -> RangeInclusive<u32> {
+        self.num_digits(*range.start())..=self.num_digits(*range.end())
+    }
+
+    /// Write `x` in this radix, using lowercase letters for digits above 9.
+    pub fn format(self, mut x: u128) -> String {
+        let radix = self.0 as u128;
+        let mut digits = Vec::new();
+        loop {
+            digits.push(char::from_digit((x % radix) as u32, self.0).unwrap());
+            x /= radix;
+            if x == 0 {
+                break;
+            }
+        }
+        digits.iter().rev().collect()
+    }
+}
 
 #[derive(Debug, Clone)]
-struct RangeSet(Vec<RangeInclusive<usize>>);
+pub struct RangeSet {
+    ranges: Vec<RangeInclusive<usize>>,
+    radix: Radix,
+}
 impl RangeSet {
-    fn new(input: &str) -> Self {
-        let mut ranges: Vec<_> = input
+    pub fn new(input: &str) -> Self {
+        Self::with_radix(input, Radix::DECIMAL)
+    }
+
+    /// Parse ranges whose bounds are written in `radix`.
+    pub fn with_radix(input: &str, radix: Radix) -> Self {
+        // Widened to half-open ranges so an end of `usize::MAX` still fits.
+        let mut ranges: Vec<Range<u128>> = input
             .split(',')
             .map(|r| {
                 let (start, end) = r.split_once('-').unwrap();
-                RangeInclusive::new(start.parse().unwrap(), end.parse().unwrap())
+                let start = usize::from_str_radix(start, radix.get()).unwrap();
+                let end = usize::from_str_radix(end, radix.get()).unwrap();
+                start as u128..end as u128 + 1
             })
             .collect();
 
-        // Sort the ranges by start key
-        ranges.sort_by_key(|r| *r.start());
+        // Merge overlapping ranges so no ID is visited twice.
+        ranges::normalize(&mut ranges);
+        let ranges = ranges
+            .into_iter()
+            .map(|range| range.start as usize..=(range.end - 1) as usize)
+            .collect();
+        RangeSet { ranges, radix }
+    }
+
+    pub fn ranges(&self) -> &[RangeInclusive<usize>] {
+        &self.ranges
+    }
 
-        // Assert none of the ranges overlap
-        for pair in ranges.windows(2) {
-            if *pair[0].end() >= *pair[1].start() {
-                panic!("{:?} overlaps with {:?}", pair[0], pair[1]);
-            }
+    pub fn radix(&self) -> Radix {
+        self.radix
+    }
+
+    /// Count and sum every ID in the set whose digits in the set's radix
+    /// match `pattern`.
+    pub fn scan(&self, pattern: &impl DigitPattern) -> Scan {
+        self.ranges()
+            .iter()
+            .map(|range| pattern.scan(range, self.radix))
+            .sum()
+    }
+
+    /// List every ID in each range that `min_repeats` matches.
+    pub fn report(&self, min_repeats: MinRepeats) -> Report {
+        let ranges = self
+            .ranges()
+            .iter()
+            .map(|range| RangeReport {
+                range: range.clone(),
+                invalid_ids: repeating_ids(range, self.radix, min_repeats.0),
+            })
+            .collect();
+        Report {
+            radix: self.radix,
+            ranges,
+        }
+    }
+}
+
+/// The number of IDs that matched a pattern and their sum.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Scan {
+    pub count: u128,
+    pub sum: u128,
+}
+impl Scan {
+    fn of(value: u128) -> Self {
+        Scan {
+            count: 1,
+            sum: value,
         }
-        RangeSet(ranges)
     }
+}
+impl AddAssign for Scan {
+    fn add_assign(&mut self, rhs: Self) {
+        self.count += rhs.count;
+        self.sum += rhs.sum;
+    }
+}
+impl Sub for Scan {
+    type Output = Scan;
 
-    fn ranges(&self) -> &[RangeInclusive<usize>] {
-        &self.0
+    fn sub(self, rhs: Self) -> Self::Output {
+        Scan {
+            count: self.count - rhs.count,
+            sum: self.sum - rhs.sum,
+        }
+    }
+}
+impl std::iter::Sum for Scan {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        let mut total = Scan::default();
+        for scan in iter {
+            total += scan;
+        }
+        total
     }
 }
 
-// repeat_seq(87, 1) -> 8787
-// repeat_seq(123, 2) -> 123123123
-// repeat_seq(67, 4) -> 6767676767
-fn repeat_seq(seq: usize, times: usize) -> usize {
-    let num_digits = seq.ilog10() + 1;
-    let shift = 10usize.pow(num_digits);
-    let mut result = seq;
-    for _ in 0..times {
-        result = result * shift + seq;
+/// A rule over the digits of an ID written in some radix.
+pub trait DigitPattern {
+    /// Returns true if `value` follows the rule when written in `radix`.
+    fn matches(&self, value: usize, radix: Radix) -> bool;
+
+    /// Count and sum the values in `range` that follow the rule when written
+    /// in `radix`.
+    ///
+    /// The default tests every value in the range. Patterns that can
+    /// enumerate their matches directly override this.
+    fn scan(&self, range: &RangeInclusive<usize>, radix: Radix) -> Scan {
+        range
+            .clone()
+            .filter(|&value| self.matches(value, radix))
+            .map(|value| Scan::of(value as u128))
+            .sum()
     }
-    result
 }
 
-// return the upper half digits of a value.
-// get_seq(1234)   -> 12
-// get_seq(987654) -> 987
-//
-// if the value has an odd number of digits round up the half and return the
-// lowest value that consumes that many digits.
-// get_seq(987)   -> 10
-// get_seq(12345) -> 100
-fn get_seq(x: usize) -> usize {
-    let num_digits = x.ilog10() + 1;
-    let base = 10usize.pow(num_digits / 2);
-    if num_digits.is_multiple_of(2) {
-        x / base
+// The value that repeating a `seq_digits` long sequence to fill `num_digits`
+// digits multiplies the sequence by. It's 1 followed by `seq_digits - 1` zeros
+// repeated, e.g. 10101 for 3 repeats of 2 decimal digits.
+fn repeat_multiplier(radix: Radix, num_digits: u32, seq_digits: u32) -> u128 {
+    (radix.pow(num_digits) - 1) / (radix.pow(seq_digits) - 1)
+}
+
+// The smallest `seq_digits` long sequence that can be repeated to fill
+// `num_digits` digits. Only a sequence that is the whole value can be 0.
+fn smallest_seq(radix: Radix, num_digits: u32, seq_digits: u32) -> u128 {
+    if seq_digits == num_digits {
+        radix.min_value(seq_digits)
     } else {
-        base
+        radix.pow(seq_digits - 1)
+    }
+}
+
+// count and sum the values in `range` that have exactly `num_digits` digits
+// and consist of a `seq_digits` long sequence repeated to fill them.
+//
+// Every such value is `seq * multiplier`, so the values form an arithmetic
+// series over the sequences that land in range.
+fn scan_repeated_seqs(
+    range: &RangeInclusive<usize>,
+    radix: Radix,
+    num_digits: u32,
+    seq_digits: u32,
+) -> Scan {
+    let multiplier = repeat_multiplier(radix, num_digits, seq_digits);
+    let min_seq = std::cmp::max(
+        smallest_seq(radix, num_digits, seq_digits),
+        (*range.start() as u128).div_ceil(multiplier),
+    );
+    let max_seq = std::cmp::min(radix.pow(seq_digits) - 1, *range.end() as u128 / multiplier);
+    if min_seq > max_seq {
+        return Scan::default();
+    }
+    let count = max_seq - min_seq + 1;
+    Scan {
+        count,
+        sum: multiplier * ((min_seq + max_seq) * count / 2),
+    }
+}
+
+/// IDs made of a sequence of digits repeated exactly `n` times.
+#[derive(Debug, Clone, Copy)]
+pub struct Repeats(u32);
+impl Repeats {
+    pub fn new(n: u32) -> Self {
+        assert!(n > 0, "a sequence must repeat at least once");
+        Repeats(n)
+    }
+}
+impl DigitPattern for Repeats {
+    fn matches(&self, value: usize, radix: Radix) -> bool {
+        let num_digits = radix.num_digits(value);
+        num_digits.is_multiple_of(self.0)
+            && (value as u128).is_multiple_of(repeat_multiplier(
+                radix,
+                num_digits,
+                num_digits / self.0,
+            ))
+    }
+
+    fn scan(&self, range: &RangeInclusive<usize>, radix: Radix) -> Scan {
+        radix
+            .digit_lengths(range)
+            .filter(|num_digits| num_digits.is_multiple_of(self.0))
+            .map(|num_digits| scan_repeated_seqs(range, radix, num_digits, num_digits / self.0))
+            .sum()
+    }
+}
+
+/// IDs made of a sequence of digits repeated `n` or more times.
+#[derive(Debug, Clone, Copy)]
+pub struct MinRepeats(u32);
+impl MinRepeats {
+    pub fn new(n: u32) -> Self {
+        assert!(n > 0, "a sequence must repeat at least once");
+        MinRepeats(n)
     }
 }
+impl DigitPattern for MinRepeats {
+    fn matches(&self, value: usize, radix: Radix) -> bool {
+        is_repeating_seq(value, radix, self.0)
+    }
+
+    fn scan(&self, range: &RangeInclusive<usize>, radix: Radix) -> Scan {
+        radix
+            .digit_lengths(range)
+            .map(|num_digits| {
+                // A value whose shortest repeating sequence is `n` digits long
+                // is also a repeat of every multiple of `n` that divides
+                // `num_digits`. Track the matches by their shortest sequence
+                // so each value is only counted once.
+                let mut by_shortest_seq: Vec<(u32, Scan)> = Vec::new();
+                for seq_digits in
+                    (1..=num_digits / self.0).filter(|&seq_len| num_digits.is_multiple_of(seq_len))
+                {
+                    let counted_by_shorter: Scan = by_shortest_seq
+                        .iter()
+                        .filter(|&&(shorter, _)| seq_digits.is_multiple_of(shorter))
+                        .map(|&(_, scan)| scan)
+                        .sum();
+                    let scan = scan_repeated_seqs(range, radix, num_digits, seq_digits)
+                        - counted_by_shorter;
+                    by_shortest_seq.push((seq_digits, scan));
+                }
+                by_shortest_seq.into_iter().map(|(_, scan)| scan).sum()
+            })
+            .sum()
+    }
+}
+
+/// IDs that read the same forwards and backwards.
+#[derive(Debug, Clone, Copy)]
+pub struct Palindrome;
+impl DigitPattern for Palindrome {
+    fn matches(&self, value: usize, radix: Radix) -> bool {
+        let digits = radix.digits(value);
+        digits.iter().eq(digits.iter().rev())
+    }
+
+    // Every palindrome is determined by its first half, so only the halves
+    // that could land in range are mirrored.
+    fn scan(&self, range: &RangeInclusive<usize>, radix: Radix) -> Scan {
+        let base = radix.get() as u128;
+        radix
+            .digit_lengths(range)
+            .map(|num_digits| {
+                let band = radix.digits_band(range, num_digits);
+                let half_digits = num_digits.div_ceil(2);
+                let shift = radix.pow(num_digits - half_digits);
+                (band.start() / shift..=band.end() / shift)
+                    .map(|half| {
+                        let mut mirrored = half;
+                        let mut rest = if num_digits % 2 == 1 {
+                            half / base
+                        } else {
+                            half
+                        };
+                        while rest > 0 {
+                            mirrored = mirrored * base + rest % base;
+                            rest /= base;
+                        }
+                        mirrored
+                    })
+                    .filter(|value| band.contains(value))
+                    .map(Scan::of)
+                    .sum()
+            })
+            .sum()
+    }
+}
+
+/// IDs whose digits never decrease, or never increase, from left to right.
+#[derive(Debug, Clone, Copy)]
+pub enum Monotone {
+    NonDecreasing,
+    NonIncreasing,
+}
+impl Monotone {
+    fn next_digits(&self, last: u8, radix: Radix) -> RangeInclusive<u8> {
+        match self {
+            Monotone::NonDecreasing => last..=(radix.get() - 1) as u8,
+            Monotone::NonIncreasing => 0..=last,
+        }
+    }
+
+    // Build values digit by digit, abandoning any prefix whose completions
+    // can't land in `band`.
+    fn scan_prefix(
+        &self,
+        band: &RangeInclusive<u128>,
+        radix: Radix,
+        prefix: u128,
+        remaining: u32,
+    ) -> Scan {
+        let base = radix.get() as u128;
+        let shift = radix.pow(remaining);
+        let lowest = prefix * shift;
+        let highest = lowest + shift - 1;
+        if highest < *band.start() || lowest > *band.end() {
+            return Scan::default();
+        }
+        if remaining == 0 {
+            return Scan::of(prefix);
+        }
+        self.next_digits((prefix % base) as u8, radix)
+            .map(|digit| {
+                self.scan_prefix(band, radix, prefix * base + digit as u128, remaining - 1)
+            })
+            .sum()
+    }
+}
+impl DigitPattern for Monotone {
+    fn matches(&self, value: usize, radix: Radix) -> bool {
+        radix
+            .digits(value)
+            .windows(2)
+            .all(|pair| self.next_digits(pair[0], radix).contains(&pair[1]))
+    }
+
+    fn scan(&self, range: &RangeInclusive<usize>, radix: Radix) -> Scan {
+        radix
+            .digit_lengths(range)
+            .map(|num_digits| {
+                let band = radix.digits_band(range, num_digits);
+                // Only a single digit value can start with 0.
+                let lowest_first = if num_digits == 1 { 0 } else { 1 };
+                (lowest_first..radix.get() as u128)
+                    .map(|first| self.scan_prefix(&band, radix, first, num_digits - 1))
+                    .sum()
+            })
+            .sum()
+    }
+}
+
+/// IDs that start with a sequence of at least `min_len` digits that is
+/// immediately repeated, e.g. 1212 or 123123456 for a `min_len` of 2.
+#[derive(Debug, Clone, Copy)]
+pub struct RepeatedPrefix(u32);
+impl RepeatedPrefix {
+    pub fn new(min_len: u32) -> Self {
+        assert!(
+            min_len > 0,
+            "a repeated prefix must have at least one digit"
+        );
+        RepeatedPrefix(min_len)
+    }
+}
+impl DigitPattern for RepeatedPrefix {
+    fn matches(&self, value: usize, radix: Radix) -> bool {
+        let digits = radix.digits(value);
+        (self.0 as usize..=digits.len() / 2).any(|len| digits[..len] == digits[len..len * 2])
+    }
+}
+
+/// An ID made of `unit` repeated `repeats` times, where `unit` is the
+/// shortest sequence that does so.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidId {
+    pub id: usize,
+    pub unit: usize,
+    pub repeats: u32,
+}
+
+/// The invalid IDs found in a single range.
+#[derive(Debug, Clone)]
+pub struct RangeReport {
+    pub range: RangeInclusive<usize>,
+    pub invalid_ids: Vec<InvalidId>,
+}
+impl RangeReport {
+    pub fn subtotal(&self) -> u128 {
+        self.invalid_ids
+            .iter()
+            .map(|invalid| invalid.id as u128)
+            .sum()
+    }
+}
+
+/// The invalid IDs found in every range of a `RangeSet`.
+///
+/// Displays as an indented text listing; `to_csv` gives one row per ID
+/// followed by a subtotal row per range. Every number but the repeat counts
+/// is written in the set's radix.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub radix: Radix,
+    pub ranges: Vec<RangeReport>,
+}
+impl Report {
+    pub fn total(&self) -> u128 {
+        self.ranges.iter().map(RangeReport::subtotal).sum()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let fmt = |x: usize| self.radix.format(x as u128);
+        let mut csv = String::from("range_start,range_end,id,unit,repeats,subtotal\n");
+        for range_report in &self.ranges {
+            let start = fmt(*range_report.range.start());
+            let end = fmt(*range_report.range.end());
+            for invalid in &range_report.invalid_ids {
+                csv += &format!(
+                    "{start},{end},{},{},{},\n",
+                    fmt(invalid.id),
+                    fmt(invalid.unit),
+                    invalid.repeats
+                );
+            }
+            csv += &format!(
+                "{start},{end},,,,{}\n",
+                self.radix.format(range_report.subtotal())
+            );
+        }
+        csv
+    }
+}
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fmt = |x: usize| self.radix.format(x as u128);
+        for range_report in &self.ranges {
+            writeln!(
+                f,
+                "{}-{}: {} invalid, subtotal {}",
+                fmt(*range_report.range.start()),
+                fmt(*range_report.range.end()),
+                range_report.invalid_ids.len(),
+                self.radix.format(range_report.subtotal())
+            )?;
+            for invalid in &range_report.invalid_ids {
+                writeln!(
+                    f,
+                    "  {} = {} x {}",
+                    fmt(invalid.id),
+                    fmt(invalid.unit),
+                    invalid.repeats
+                )?;
+            }
+        }
+        writeln!(f, "total {}", self.radix.format(self.total()))
+    }
+}
+
+// every ID in `range` that is a sequence repeated at least `min_repeats`
+// times, in ascending order.
+fn repeating_ids(range: &RangeInclusive<usize>, radix: Radix, min_repeats: u32) -> Vec<InvalidId> {
+    let mut invalid_ids = Vec::new();
+    for num_digits in radix.digit_lengths(range) {
+        for seq_digits in
+            (1..=num_digits / min_repeats).filter(|&seq_len| num_digits.is_multiple_of(seq_len))
+        {
+            let multiplier = repeat_multiplier(radix, num_digits, seq_digits);
+            let band = radix.digits_band(range, num_digits);
+            let min_seq = std::cmp::max(
+                smallest_seq(radix, num_digits, seq_digits),
+                band.start().div_ceil(multiplier),
+            );
+            let max_seq = std::cmp::min(radix.pow(seq_digits) - 1, band.end() / multiplier);
+            invalid_ids.extend(
+                (min_seq..=max_seq)
+                    .map(|seq| seq as usize)
+                    // A sequence that repeats itself is found again as its
+                    // own shorter unit.
+                    .filter(|&seq| !is_repeating_seq(seq, radix, 2))
+                    .map(|seq| InvalidId {
+                        id: (seq as u128 * multiplier) as usize,
+                        unit: seq,
+                        repeats: num_digits / seq_digits,
+                    }),
+            );
+        }
+    }
+    invalid_ids.sort_by_key(|invalid| invalid.id);
+    invalid_ids
+}
 
 #[aoc_generator(day2)]
 fn parse(input: &str) -> RangeSet {
@@ -68,31 +567,20 @@ fn parse(input: &str) -> RangeSet {
 }
 
 #[aoc(day2, part1)]
-fn part1(input: &RangeSet) -> usize {
-    input
-        .ranges()
-        .iter()
-        .map(|range| -> usize {
-            let start_seq = get_seq(*range.start());
-            let end_seq = get_seq(*range.end());
-
-            (start_seq..=end_seq)
-                .map(|seq| repeat_seq(seq, 1))
-                .filter(|value| range.contains(value))
-                .sum()
-        })
-        .sum()
+fn part1(input: &RangeSet) -> u128 {
+    input.scan(&Repeats::new(2)).sum
 }
 
-// determine if `x` consists of a repeating sequence of digits.
-fn is_repeating_seq(x: usize) -> bool {
-    let num_digits = x.ilog10() + 1;
+// determine if `x` written in `radix` consists of a sequence of digits
+// repeated at least `min_repeats` times.
+fn is_repeating_seq(x: usize, radix: Radix, min_repeats: u32) -> bool {
+    let num_digits = radix.num_digits(x);
     let sequence_digits =
-        (1..=num_digits / 2).filter(|&seq_len| num_digits.is_multiple_of(seq_len));
+        (1..=num_digits / min_repeats).filter(|&seq_len| num_digits.is_multiple_of(seq_len));
     for seq_digits in sequence_digits {
-        let mut x = x;
-        let shift = 10usize.pow(seq_digits);
-        let seq_pattern = x / 10usize.pow(num_digits - seq_digits);
+        let mut x = x as u128;
+        let shift = radix.pow(seq_digits);
+        let seq_pattern = x / radix.pow(num_digits - seq_digits);
         loop {
             if x == 0 {
                 return true;
@@ -107,11 +595,131 @@ fn is_repeating_seq(x: usize) -> bool {
 }
 
 #[aoc(day2, part2)]
-fn part2(input: &RangeSet) -> usize {
+fn part2(input: &RangeSet) -> u128 {
+    input.scan(&MinRepeats::new(2)).sum
+}
+
+#[aoc(day2, part2, brute_force)]
+fn part2_brute_force(input: &RangeSet) -> u128 {
     input
         .ranges()
         .iter()
         .flat_map(|range| range.clone())
-        .filter(|&value| is_repeating_seq(value))
+        .filter(|&value| is_repeating_seq(value, input.radix(), 2))
+        .map(|value| value as u128)
         .sum()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Rng;
+
+    // Count and sum the values of `range` one by one.
+    fn scan_each(pattern: &impl DigitPattern, range: &RangeInclusive<usize>, radix: Radix) -> Scan {
+        range
+            .clone()
+            .filter(|&value| pattern.matches(value, radix))
+            .map(|value| Scan::of(value as u128))
+            .sum()
+    }
+
+    #[test]
+    fn fast_scans_match_each_value() {
+        let mut rng = Rng::new(28);
+        for radix in [2, 7, 10, 16, 36].map(Radix::new) {
+            for _ in 0..300 {
+                let start = match rng.below(3) {
+                    0 => 0,
+                    _ => {
+                        let num_digits = rng.below(5) as u32 + 1;
+                        rng.below(radix.pow(num_digits) as u64) as usize
+                    }
+                };
+                let range = start..=start + rng.below(3000) as usize;
+                for n in 1..=3 {
+                    assert_eq!(
+                        Repeats::new(n).scan(&range, radix),
+                        scan_each(&Repeats::new(n), &range, radix)
+                    );
+                    assert_eq!(
+                        MinRepeats::new(n).scan(&range, radix),
+                        scan_each(&MinRepeats::new(n), &range, radix)
+                    );
+                }
+                assert_eq!(
+                    Palindrome.scan(&range, radix),
+                    scan_each(&Palindrome, &range, radix)
+                );
+                for monotone in [Monotone::NonDecreasing, Monotone::NonIncreasing] {
+                    assert_eq!(
+                        monotone.scan(&range, radix),
+                        scan_each(&monotone, &range, radix)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ranges_are_merged() {
+        let max = usize::MAX;
+        let ranges = RangeSet::new(&format!("5-10,11-12,1-3,20-15,{}-{max},2-2", max - 1));
+        assert_eq!(ranges.ranges(), [1..=3, 5..=12, max - 1..=max]);
+    }
+
+    #[test]
+    fn report_writes_subtotals_in_the_radix() {
+        let report = RangeSet::with_radix("a-ff", Radix::new(16)).report(MinRepeats::new(2));
+        assert_eq!(report.total(), 0x11 * (1..=15).sum::<u128>());
+        let csv = report.to_csv();
+        assert!(csv.starts_with("range_start,range_end,id,unit,repeats,subtotal\na,ff,11,1,2,\n"));
+        assert!(csv.ends_with("a,ff,,,,7f8\n"));
+        assert!(report.to_string().ends_with("ff = f x 2\ntotal 7f8\n"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_repeats_are_rejected() {
+        MinRepeats::new(0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_prefixes_are_rejected() {
+        RepeatedPrefix::new(0);
+    }
+
+    #[test]
+    fn repeated_prefixes_match() {
+        let radix = Radix::new(10);
+        let matching = |min_len| {
+            [11, 1212, 123123456, 1231234, 12134, 99]
+                .into_iter()
+                .filter(|&value| RepeatedPrefix::new(min_len).matches(value, radix))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matching(1), [11, 1212, 123123456, 1231234, 99]);
+        assert_eq!(matching(2), [1212, 123123456, 1231234]);
+        assert_eq!(matching(3), [123123456, 1231234]);
+    }
+
+    #[test]
+    fn min_repeats_matches_brute_force_at_every_magnitude() {
+        let mut rng = Rng::new(2);
+        for num_digits in 1..=usize::MAX.ilog10() + 1 {
+            for _ in 0..200 {
+                let lowest = 10usize.pow(num_digits - 1);
+                let start =
+                    lowest.saturating_add(rng.below((lowest as u64).saturating_mul(9)) as usize);
+                let end = start.saturating_add(rng.below(2000) as usize);
+                let ranges = RangeSet::new(&format!("{start}-{end}"));
+                assert_eq!(part2(&ranges), part2_brute_force(&ranges), "{start}-{end}");
+            }
+        }
+        for len in [0, 1, 10, 5000] {
+            let ranges = RangeSet::new(&format!("{}-{}", usize::MAX - len, usize::MAX));
+            assert_eq!(part2(&ranges), part2_brute_force(&ranges));
+        }
+    }
+}
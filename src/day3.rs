@@ -1,6 +1,137 @@
 use aoc_runner_derive::aoc;
+use num_bigint::BigUint;
 
-fn max_joltage(input: &str, num_batteries: usize) -> usize {
+/// A joltage kept as its decimal digits, most significant first, so any
+/// number of batteries can be turned on without overflowing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Joltage(Vec<u8>);
+impl Joltage {
+    pub fn digits(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The joltage as a `usize`, or None if it doesn't fit.
+    pub fn to_usize(&self) -> Option<usize> {
+        self.0.iter().try_fold(0usize, |result, &digit| {
+            result.checked_mul(10)?.checked_add(digit as usize)
+        })
+    }
+
+    pub fn to_biguint(&self) -> BigUint {
+        BigUint::from_radix_be(&self.0, 10).unwrap()
+    }
+}
+impl std::fmt::Display for Joltage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for &digit in &self.0 {
+            write!(f, "{digit}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The batteries turned on in a bank and the joltage they produce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// Positions in the bank of the batteries turned on, in ascending order.
+    pub indices: Vec<usize>,
+    pub joltage: Joltage,
+}
+
+impl Selection {
+    fn new(bank: &[u8], indices: Vec<usize>) -> Self {
+        let joltage = Joltage(indices.iter().map(|&idx| bank[idx]).collect());
+        Selection { indices, joltage }
+    }
+}
+
+/// The joltage of each battery in a bank.
+fn parse_bank(input: &str) -> Vec<u8> {
+    input
+        .bytes()
+        .map(|b| {
+            assert!(b.is_ascii_digit(), "invalid battery: {:?}", b as char);
+            b - b'0'
+        })
+        .collect()
+}
+
+// Scan `bank` once, keeping a stack of the batteries chosen so far. A battery
+// knocks the top of the stack off while `replaces(top, battery)` holds, as
+// long as enough batteries remain to still reach `num_batteries`.
+fn select_by_stack(
+    bank: &[u8],
+    offset: usize,
+    num_batteries: usize,
+    replaces: impl Fn(u8, u8) -> bool,
+) -> Vec<usize> {
+    let mut can_drop = bank.len() - offset - num_batteries;
+    let mut chosen: Vec<usize> = Vec::with_capacity(bank.len() - offset);
+    for (idx, &battery) in bank.iter().enumerate().skip(offset) {
+        while can_drop > 0
+            && chosen
+                .last()
+                .is_some_and(|&top| replaces(bank[top], battery))
+        {
+            chosen.pop();
+            can_drop -= 1;
+        }
+        chosen.push(idx);
+    }
+    chosen.truncate(num_batteries);
+    chosen
+}
+
+/// Choose the `num_batteries` batteries in the bank that produce the largest
+/// joltage, keeping their order.
+pub fn select_max_joltage(input: &str, num_batteries: usize) -> Selection {
+    let bank = parse_bank(input);
+    let indices = select_by_stack(&bank, 0, num_batteries, |top, battery| top < battery);
+    Selection::new(&bank, indices)
+}
+
+/// The largest number that can be formed by turning on `num_batteries` of the
+/// batteries in the bank, keeping their order.
+pub fn max_joltage(input: &str, num_batteries: usize) -> Joltage {
+    select_max_joltage(input, num_batteries).joltage
+}
+
+/// Render every bank in `input` with a line beneath it marking the batteries
+/// turned on to reach the max joltage, followed by that joltage. With two
+/// batteries:
+///
+/// ```text
+/// 818181911112111
+///       ^    ^    92
+/// ```
+pub fn render_selections(input: &str, num_batteries: usize) -> String {
+    let mut rendered = String::new();
+    for line in input.split('\n') {
+        let selection = select_max_joltage(line, num_batteries);
+        let mut markers = vec![b' '; line.len()];
+        for &idx in &selection.indices {
+            markers[idx] = b'^';
+        }
+        rendered += line;
+        rendered.push('\n');
+        rendered += std::str::from_utf8(&markers).unwrap();
+        rendered += &format!(" {}\n", selection.joltage);
+    }
+    rendered
+}
+
+/// Sum the max joltage of every bank in `input`.
+pub fn total_max_joltage(input: &str, num_batteries: usize) -> BigUint {
+    input
+        .split('\n')
+        .map(|line| max_joltage(line, num_batteries).to_biguint())
+        .sum()
+}
+
+/// Same as `max_joltage`, but rescans the remaining window of the bank for
+/// every battery chosen. O(n * num_batteries) and limited to joltages that
+/// fit in a `usize`.
+pub fn max_joltage_rescan(input: &str, num_batteries: usize) -> usize {
     let input = input.as_bytes();
     let mut result = 0;
     let mut next_battery_start_idx = 0;
@@ -25,12 +156,276 @@ fn max_joltage(input: &str, num_batteries: usize) -> usize {
     result
 }
 
+/// Whether the smallest joltage may start with a battery of joltage 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeadingZeros {
+    Allow,
+    Forbid,
+}
+
+/// Choose the `num_batteries` batteries in the bank that produce the smallest
+/// joltage, keeping their order. Returns None if `leading_zeros` forbids a
+/// leading 0 and the bank has no non-zero battery early enough to lead.
+pub fn select_min_joltage(
+    input: &str,
+    num_batteries: usize,
+    leading_zeros: LeadingZeros,
+) -> Option<Selection> {
+    let bank = parse_bank(input);
+    let smaller = |top, battery| top > battery;
+    let indices = match leading_zeros {
+        LeadingZeros::Allow => select_by_stack(&bank, 0, num_batteries, smaller),
+        LeadingZeros::Forbid if num_batteries == 0 => Vec::new(),
+        LeadingZeros::Forbid => {
+            // Lead with the leftmost smallest non-zero battery that leaves
+            // enough batteries after it, then minimize the rest.
+            let lead_candidates = &bank[..=bank.len() - num_batteries];
+            let lead = (1..=9).find_map(|joltage| {
+                lead_candidates
+                    .iter()
+                    .position(|&battery| battery == joltage)
+            })?;
+            let mut indices = vec![lead];
+            indices.extend(select_by_stack(&bank, lead + 1, num_batteries - 1, smaller));
+            indices
+        }
+    };
+    Some(Selection::new(&bank, indices))
+}
+
+/// The smallest number that can be formed by turning on `num_batteries` of
+/// the batteries in the bank, keeping their order.
+pub fn min_joltage(
+    input: &str,
+    num_batteries: usize,
+    leading_zeros: LeadingZeros,
+) -> Option<Joltage> {
+    select_min_joltage(input, num_batteries, leading_zeros).map(|selection| selection.joltage)
+}
+
+// For every position in `bank`, the position of the next battery of each
+// joltage at or after it. Has an extra row for the end of the bank.
+fn next_positions(bank: &[u8]) -> Vec<[Option<usize>; 10]> {
+    let mut next = vec![[None; 10]; bank.len() + 1];
+    for idx in (0..bank.len()).rev() {
+        next[idx] = next[idx + 1];
+        next[idx][bank[idx] as usize] = Some(idx);
+    }
+    next
+}
+
+/// The `rank`th largest distinct joltage that can be formed by turning on
+/// `num_batteries` of the batteries in the bank, where a `rank` of 1 is the
+/// max joltage. Returns None if there are fewer than `rank` distinct
+/// joltages.
+pub fn kth_max_joltage(input: &str, num_batteries: usize, rank: u128) -> Option<Joltage> {
+    let bank = parse_bank(input);
+    // Turning on no batteries forms just the one empty joltage, which the
+    // digit by digit search below never gets to rank.
+    if num_batteries == 0 {
+        return (rank == 1).then(|| Joltage(Vec::new()));
+    }
+    let next = next_positions(&bank);
+
+    // distinct[idx][len] is the number of distinct joltages of `len`
+    // batteries that can be formed from the batteries at or after `idx`.
+    // Each joltage is counted once, by placing every battery as far left as
+    // it can go.
+    let mut distinct = vec![vec![0u128; num_batteries + 1]; bank.len() + 1];
+    for idx in (0..=bank.len()).rev() {
+        distinct[idx][0] = 1;
+        for len in 1..=num_batteries {
+            distinct[idx][len] = next[idx]
+                .iter()
+                .flatten()
+                .map(|&pos| distinct[pos + 1][len - 1])
+                .fold(0, u128::saturating_add);
+        }
+    }
+
+    // Pick the joltage digit by digit from the largest down, skipping over
+    // every joltage that starts with a larger digit.
+    let mut rank = rank;
+    let mut idx = 0;
+    let mut joltage = Vec::with_capacity(num_batteries);
+    for len in (1..=num_batteries).rev() {
+        let (digit, pos) = (0..10).rev().find_map(|digit| {
+            let pos = next[idx][digit]?;
+            let count = distinct[pos + 1][len - 1];
+            if rank <= count {
+                Some((digit, pos))
+            } else {
+                rank -= count;
+                None
+            }
+        })?;
+        joltage.push(digit as u8);
+        idx = pos + 1;
+    }
+    Some(Joltage(joltage))
+}
+
+/// The number of different sets of `num_batteries` batteries in the bank
+/// that produce the max joltage.
+pub fn count_max_joltage_selections(input: &str, num_batteries: usize) -> u128 {
+    let bank = parse_bank(input);
+    let target = select_max_joltage(input, num_batteries).joltage.0;
+
+    // ways[len] is the number of ways to place the first `len` batteries of
+    // the max joltage among the batteries seen so far.
+    let mut ways = vec![0u128; target.len() + 1];
+    ways[0] = 1;
+    for &battery in &bank {
+        for len in (1..=target.len()).rev() {
+            if target[len - 1] == battery {
+                ways[len] = ways[len].saturating_add(ways[len - 1]);
+            }
+        }
+    }
+    ways[target.len()]
+}
+
 #[aoc(day3, part1)]
-fn part1(input: &str) -> usize {
-    input.split("\n").map(|line| max_joltage(line, 2)).sum()
+fn part1(input: &str) -> BigUint {
+    total_max_joltage(input, 2)
 }
 
 #[aoc(day3, part2)]
-fn part2(input: &str) -> usize {
-    input.split("\n").map(|line| max_joltage(line, 12)).sum()
+fn part2(input: &str) -> BigUint {
+    total_max_joltage(input, 12)
+}
+
+#[aoc(day3, part2, rescan)]
+fn part2_rescan(input: &str) -> usize {
+    input
+        .split("\n")
+        .map(|line| max_joltage_rescan(line, 12))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::testing::Rng;
+
+    #[test]
+    fn no_batteries_select_nothing() {
+        for leading_zeros in [LeadingZeros::Allow, LeadingZeros::Forbid] {
+            for bank in ["", "0", "9071"] {
+                let selection = select_min_joltage(bank, 0, leading_zeros).unwrap();
+                assert!(selection.indices.is_empty());
+                assert!(selection.joltage.digits().is_empty());
+            }
+        }
+    }
+
+    // Every joltage of `num_batteries` batteries in `bank`, with the number
+    // of selections forming each, from smallest to largest.
+    fn all_joltages(bank: &str, num_batteries: usize) -> BTreeMap<Vec<u8>, u128> {
+        let bank = parse_bank(bank);
+        let mut joltages = BTreeMap::new();
+        for subset in 0u32..1 << bank.len() {
+            if subset.count_ones() as usize == num_batteries {
+                let joltage = (0..bank.len())
+                    .filter(|&idx| subset & 1 << idx != 0)
+                    .map(|idx| bank[idx])
+                    .collect();
+                *joltages.entry(joltage).or_insert(0) += 1;
+            }
+        }
+        joltages
+    }
+
+    #[test]
+    fn joltages_match_every_subset() {
+        let mut rng = Rng::new(33);
+        for _ in 0..300 {
+            // Few distinct digits make for many repeated joltages.
+            let num_digits = [2, 3, 10][rng.below(3) as usize];
+            let bank_len = rng.below(9) as usize;
+            let bank: String = (0..bank_len)
+                .map(|_| char::from(b'0' + rng.below(num_digits) as u8))
+                .collect();
+            for num_batteries in 0..=bank_len {
+                let joltages = all_joltages(&bank, num_batteries);
+                let context = format!("{bank:?} with {num_batteries} batteries");
+
+                let (max, &max_count) = joltages.last_key_value().unwrap();
+                let selection = select_max_joltage(&bank, num_batteries);
+                assert_eq!(selection.joltage.digits(), max, "{context}");
+                assert!(selection.indices.is_sorted(), "{context}");
+                assert_eq!(
+                    count_max_joltage_selections(&bank, num_batteries),
+                    max_count,
+                    "{context}"
+                );
+                for (rank, joltage) in joltages.keys().rev().enumerate() {
+                    let kth = kth_max_joltage(&bank, num_batteries, rank as u128 + 1);
+                    assert_eq!(
+                        kth.as_ref().map(Joltage::digits),
+                        Some(&joltage[..]),
+                        "{context}"
+                    );
+                }
+                assert_eq!(
+                    kth_max_joltage(&bank, num_batteries, joltages.len() as u128 + 1),
+                    None,
+                    "{context}"
+                );
+
+                let min = joltages.first_key_value().unwrap().0;
+                let min_allowed = min_joltage(&bank, num_batteries, LeadingZeros::Allow);
+                assert_eq!(
+                    min_allowed.as_ref().map(Joltage::digits),
+                    Some(&min[..]),
+                    "{context}"
+                );
+                let min_forbidden = joltages.keys().find(|joltage| joltage.first() != Some(&0));
+                let selection = select_min_joltage(&bank, num_batteries, LeadingZeros::Forbid);
+                assert_eq!(
+                    selection
+                        .as_ref()
+                        .map(|selection| selection.joltage.digits()),
+                    min_forbidden.map(|joltage| &joltage[..]),
+                    "{context}"
+                );
+                if let Some(selection) = selection {
+                    assert!(selection.indices.is_sorted(), "{context}");
+                    let digits: Vec<u8> = selection
+                        .indices
+                        .iter()
+                        .map(|&idx| bank.as_bytes()[idx] - b'0')
+                        .collect();
+                    assert_eq!(selection.joltage.digits(), digits, "{context}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_batteries_form_one_joltage() {
+        assert_eq!(kth_max_joltage("123", 0, 1), Some(Joltage(Vec::new())));
+        assert_eq!(kth_max_joltage("123", 0, 2), None);
+        assert_eq!(count_max_joltage_selections("123", 0), 1);
+    }
+
+    #[test]
+    fn max_joltage_matches_rescan() {
+        let mut rng = Rng::new(31);
+        for num_batteries in 1..=19 {
+            for _ in 0..100 {
+                let bank_len = num_batteries + rng.below(60) as usize;
+                let bank: String = (0..bank_len)
+                    .map(|_| char::from(b'0' + rng.below(10) as u8))
+                    .collect();
+                assert_eq!(
+                    max_joltage(&bank, num_batteries).to_usize(),
+                    Some(max_joltage_rescan(&bank, num_batteries)),
+                    "{bank} with {num_batteries} batteries"
+                );
+            }
+        }
+    }
 }
@@ -1,9 +1,10 @@
 use aoc_runner_derive::aoc;
+use image::{Rgb, RgbImage};
 
-#[derive(Debug)]
-struct Grid {
+#[derive(Debug, Clone)]
+pub struct Grid<T = bool> {
     row_len: usize,
-    data: Vec<bool>,
+    data: Vec<T>,
 }
 
 impl Grid {
@@ -16,47 +17,446 @@ impl Grid {
         }
         Grid { row_len, data }
     }
+}
+
+impl<T> Grid<T> {
+    pub fn row_len(&self) -> usize {
+        self.row_len
+    }
 
-    fn col_len(&self) -> usize {
+    pub fn col_len(&self) -> usize {
         self.data.len() / self.row_len
     }
 
-    fn neighbors(&self, pos: usize) -> impl Iterator<Item = usize> {
-        let x = pos % self.row_len;
-        let y = pos / self.row_len;
-        let x = x as isize;
-        let y = y as isize;
+    /// The cells in row-major order.
+    pub fn cells(&self) -> &[T] {
+        &self.data
+    }
+
+    /// The positions of the cells around `pos` under `adjacency`.
+    ///
+    /// On a toroidal grid smaller than the neighborhood, a cell reached by
+    /// more than one offset is returned once per offset.
+    fn neighbors<'a>(
+        &self,
+        pos: usize,
+        adjacency: &'a Adjacency,
+    ) -> impl Iterator<Item = usize> + use<'a, T> {
+        let x = (pos % self.row_len) as isize;
+        let y = (pos / self.row_len) as isize;
         let row_len = self.row_len as isize;
         let col_len = self.col_len() as isize;
+        let topology = adjacency.topology;
 
-        (x - 1..=x + 1)
-            .flat_map(move |x| (y - 1..=y + 1).map(move |y| (x, y)))
-            .filter(move |&(nx, ny)| {
-                nx >= 0 && ny >= 0 && nx < row_len && ny < col_len && !(nx == x && ny == y)
+        adjacency.offsets[(y % 2) as usize]
+            .iter()
+            .filter_map(move |&(dx, dy)| {
+                let (nx, ny) = (x + dx, y + dy);
+                match topology {
+                    Topology::Bounded => {
+                        (nx >= 0 && ny >= 0 && nx < row_len && ny < col_len).then_some((nx, ny))
+                    }
+                    Topology::Toroidal => Some((nx.rem_euclid(row_len), ny.rem_euclid(col_len))),
+                }
             })
+            .filter(move |&(nx, ny)| !(nx == x && ny == y))
             .map(move |(x, y)| (y * row_len + x) as usize)
     }
 }
 
-#[aoc(day4, part1)]
-fn part1(input: &str) -> usize {
-    let grid = Grid::new(input);
-    grid.data
-        .iter()
-        .copied()
-        .enumerate()
-        .filter(|&(_, occupied)| occupied)
-        .filter(|&(idx, _)| {
-            grid.neighbors(idx)
+/// Which cells around a roll count as its neighbors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighborhood {
+    /// The 4 orthogonally adjacent cells.
+    VonNeumann,
+    /// The 8 orthogonally and diagonally adjacent cells.
+    Moore,
+    /// Every cell within `n` steps in any direction, including diagonals.
+    Radius(usize),
+    /// The 6 cells around a hexagon when odd rows are shifted half a cell to
+    /// the right. A toroidal grid needs an even number of rows so rows keep
+    /// alternating across the wrap.
+    HexOffset,
+}
+
+impl Neighborhood {
+    // The (dx, dy) offsets of the neighbors of a cell in an even or odd row.
+    fn offsets(self, odd_row: bool) -> Vec<(isize, isize)> {
+        match self {
+            Neighborhood::VonNeumann => vec![(0, -1), (-1, 0), (1, 0), (0, 1)],
+            Neighborhood::Moore => Neighborhood::Radius(1).offsets(odd_row),
+            Neighborhood::Radius(radius) => {
+                let radius = radius as isize;
+                (-radius..=radius)
+                    .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+                    .filter(|&offset| offset != (0, 0))
+                    .collect()
+            }
+            Neighborhood::HexOffset => {
+                let shift = odd_row as isize;
+                vec![
+                    (shift - 1, -1),
+                    (shift, -1),
+                    (-1, 0),
+                    (1, 0),
+                    (shift - 1, 1),
+                    (shift, 1),
+                ]
+            }
+        }
+    }
+}
+
+/// What happens at the edges of the grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// Cells past the edge don't exist.
+    Bounded,
+    /// The grid wraps around, so the cells past one edge are those along the
+    /// opposite edge.
+    Toroidal,
+}
+
+// The cells around each cell of a grid with some neighborhood and topology.
+#[derive(Debug, Clone)]
+struct Adjacency {
+    topology: Topology,
+    // The neighbor offsets for cells in even and odd rows.
+    offsets: [Vec<(isize, isize)>; 2],
+}
+
+impl Adjacency {
+    fn new(neighborhood: Neighborhood, topology: Topology) -> Self {
+        Adjacency {
+            topology,
+            offsets: [neighborhood.offsets(false), neighborhood.offsets(true)],
+        }
+    }
+}
+
+/// The rules deciding which rolls a forklift can access: a roll is accessible
+/// when fewer than `threshold` of its neighbors hold rolls.
+#[derive(Debug, Clone)]
+pub struct Rules {
+    adjacency: Adjacency,
+    threshold: usize,
+}
+
+impl Rules {
+    pub fn new(neighborhood: Neighborhood, topology: Topology, threshold: usize) -> Self {
+        Rules {
+            adjacency: Adjacency::new(neighborhood, topology),
+            threshold,
+        }
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+}
+
+impl Default for Rules {
+    /// A bounded grid where rolls with fewer than 4 of their 8 neighbors
+    /// occupied are accessible.
+    fn default() -> Self {
+        Rules::new(Neighborhood::Moore, Topology::Bounded, 4)
+    }
+}
+
+fn neighbor_counts(grid: &Grid, rules: &Rules) -> Vec<usize> {
+    assert!(
+        rules.adjacency.topology == Topology::Bounded
+            || rules.adjacency.offsets[0] == rules.adjacency.offsets[1]
+            || grid.col_len().is_multiple_of(2),
+        "hex neighborhoods only wrap around an even number of rows"
+    );
+    (0..grid.data.len())
+        .map(|idx| {
+            grid.neighbors(idx, &rules.adjacency)
                 .filter(|&neighbor_idx| grid.data[neighbor_idx])
                 .count()
-                < 4
         })
+        .collect()
+}
+
+/// The number of rolls a forklift can access under `rules`.
+pub fn count_accessible(input: &str, rules: &Rules) -> usize {
+    let grid = Grid::new(input);
+    let neighbor_counts = neighbor_counts(&grid, rules);
+    (0..grid.data.len())
+        .filter(|&idx| grid.data[idx] && neighbor_counts[idx] < rules.threshold)
         .count()
 }
 
+/// The number of rolls that can be removed under `rules` by repeatedly
+/// removing every accessible roll.
+pub fn count_removable(input: &str, rules: &Rules) -> usize {
+    peel(&mut Grid::new(input), rules)
+}
+
+// Remove rolls from `grid` until none are accessible under `rules`, returning
+// the number removed.
+fn peel(grid: &mut Grid, rules: &Rules) -> usize {
+    let mut neighbor_counts = neighbor_counts(grid, rules);
+
+    // Rolls are taken off the grid as soon as they're queued so that each is
+    // only queued once. Removing a roll can only make its neighbors more
+    // accessible, so the order they're processed in doesn't matter.
+    let mut to_remove: Vec<usize> = (0..grid.data.len())
+        .filter(|&idx| grid.data[idx] && neighbor_counts[idx] < rules.threshold)
+        .collect();
+    for &idx in &to_remove {
+        grid.data[idx] = false;
+    }
+    let mut num_removed = 0;
+    while let Some(idx) = to_remove.pop() {
+        num_removed += 1;
+        for neighbor_idx in grid.neighbors(idx, &rules.adjacency) {
+            neighbor_counts[neighbor_idx] -= 1;
+            if grid.data[neighbor_idx] && neighbor_counts[neighbor_idx] < rules.threshold {
+                grid.data[neighbor_idx] = false;
+                to_remove.push(neighbor_idx);
+            }
+        }
+    }
+    num_removed
+}
+
+/// A group of rolls connected through their 8 surrounding cells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Component {
+    /// The positions of the rolls in row-major order.
+    pub rolls: Vec<usize>,
+    /// The top left corner of the bounding box as (x, y).
+    pub min: (usize, usize),
+    /// The bottom right corner of the bounding box as (x, y), inclusive.
+    pub max: (usize, usize),
+    /// The number of roll edges that don't touch another roll in the
+    /// component, counting the edge of the grid.
+    pub perimeter: usize,
+}
+
+impl Component {
+    pub fn size(&self) -> usize {
+        self.rolls.len()
+    }
+}
+
+/// Group the rolls left once nothing more is accessible under `rules` into
+/// 8-connected components, sorted largest first so the largest component is
+/// at index 0.
+pub fn remaining_components(input: &str, rules: &Rules) -> Vec<Component> {
+    let mut grid = Grid::new(input);
+    peel(&mut grid, rules);
+
+    let connected = Adjacency::new(Neighborhood::Moore, Topology::Bounded);
+    let edges = Adjacency::new(Neighborhood::VonNeumann, Topology::Bounded);
+    let mut visited = vec![false; grid.data.len()];
+    let mut components = Vec::new();
+    for start in 0..grid.data.len() {
+        if !grid.data[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut rolls = vec![start];
+        let mut to_visit = vec![start];
+        while let Some(idx) = to_visit.pop() {
+            for neighbor_idx in grid.neighbors(idx, &connected) {
+                if grid.data[neighbor_idx] && !visited[neighbor_idx] {
+                    visited[neighbor_idx] = true;
+                    rolls.push(neighbor_idx);
+                    to_visit.push(neighbor_idx);
+                }
+            }
+        }
+        rolls.sort_unstable();
+
+        let mut min = (usize::MAX, usize::MAX);
+        let mut max = (0, 0);
+        let mut perimeter = 0;
+        for &idx in &rolls {
+            let (x, y) = (idx % grid.row_len, idx / grid.row_len);
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+            let covered_edges = grid
+                .neighbors(idx, &edges)
+                .filter(|&neighbor_idx| grid.data[neighbor_idx])
+                .count();
+            perimeter += 4 - covered_edges;
+        }
+        components.push(Component {
+            rolls,
+            min,
+            max,
+            perimeter,
+        });
+    }
+    components.sort_by_key(|component| std::cmp::Reverse(component.size()));
+    components
+}
+
+/// The rolls stored one bit per cell with each row packed into `u64` words,
+/// bit `i` of word `w` holding column `w * 64 + i`.
+///
+/// Neighbor counts for 64 cells at a time are computed with shifts and
+/// bitwise adds, so this only supports the default forklift rules: the 8
+/// surrounding cells of a bounded grid with a threshold of 4.
+#[derive(Debug, Clone)]
+pub struct BitGrid {
+    row_len: usize,
+    words_per_row: usize,
+    words: Vec<u64>,
+}
+
+impl BitGrid {
+    pub fn new(input: &str) -> Self {
+        let row_len = input.find('\n').unwrap_or(input.len());
+        let words_per_row = row_len.div_ceil(64);
+        let mut words = Vec::new();
+        for line in input.as_bytes().split(|&b| b == b'\n') {
+            let mut row = vec![0u64; words_per_row];
+            for (x, _) in line.iter().enumerate().filter(|&(_, &b)| b == b'@') {
+                row[x / 64] |= 1 << (x % 64);
+            }
+            words.extend(row);
+        }
+        BitGrid {
+            row_len,
+            words_per_row,
+            words,
+        }
+    }
+
+    pub fn row_len(&self) -> usize {
+        self.row_len
+    }
+
+    pub fn col_len(&self) -> usize {
+        self.words.len() / self.words_per_row
+    }
+
+    fn row(&self, y: usize) -> &[u64] {
+        &self.words[y * self.words_per_row..(y + 1) * self.words_per_row]
+    }
+
+    pub fn num_rolls(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    // Write the rolls in row `y` with fewer than 4 occupied neighbors to
+    // `accessible`.
+    fn accessible_in_row(&self, y: usize, accessible: &mut [u64]) {
+        let empty = vec![0u64; self.words_per_row];
+        let above = if y > 0 { self.row(y - 1) } else { &empty };
+        let below = if y + 1 < self.col_len() {
+            self.row(y + 1)
+        } else {
+            &empty
+        };
+        let row = self.row(y);
+        for (w, accessible) in accessible.iter_mut().enumerate() {
+            // Add up the 8 neighbor planes in a 2 bit counter per cell,
+            // remembering which cells overflowed it by reaching 4.
+            let (mut ones, mut twos, mut at_least_four) = (0u64, 0u64, 0u64);
+            let [a_left, a_mid, a_right] = shifted(above, w);
+            let [r_left, _, r_right] = shifted(row, w);
+            let [b_left, b_mid, b_right] = shifted(below, w);
+            for plane in [
+                a_left, a_mid, a_right, r_left, r_right, b_left, b_mid, b_right,
+            ] {
+                let carry = ones & plane;
+                ones ^= plane;
+                at_least_four |= twos & carry;
+                twos ^= carry;
+            }
+            *accessible = row[w] & !at_least_four;
+        }
+    }
+
+    /// The number of rolls a forklift can access.
+    pub fn count_accessible(&self) -> usize {
+        let mut accessible = vec![0u64; self.words_per_row];
+        (0..self.col_len())
+            .map(|y| {
+                self.accessible_in_row(y, &mut accessible);
+                accessible
+                    .iter()
+                    .map(|word| word.count_ones() as usize)
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Remove every accessible roll until none are left, returning the number
+    /// removed.
+    pub fn remove_all_accessible(&mut self) -> usize {
+        let col_len = self.col_len();
+        let mut num_removed = 0;
+        // Only rows next to a removal can have changed accessibility.
+        let mut dirty = vec![true; col_len];
+        loop {
+            let mut removals = Vec::new();
+            for y in (0..col_len).filter(|&y| dirty[y]) {
+                let mut accessible = vec![0u64; self.words_per_row];
+                self.accessible_in_row(y, &mut accessible);
+                if accessible.iter().any(|&word| word != 0) {
+                    removals.push((y, accessible));
+                }
+            }
+            if removals.is_empty() {
+                break;
+            }
+            dirty.fill(false);
+            for (y, accessible) in removals {
+                let row = &mut self.words[y * self.words_per_row..(y + 1) * self.words_per_row];
+                for (word, accessible) in row.iter_mut().zip(&accessible) {
+                    *word &= !accessible;
+                    num_removed += accessible.count_ones() as usize;
+                }
+                let end = std::cmp::min(y + 2, col_len);
+                dirty[y.saturating_sub(1)..end].fill(true);
+            }
+        }
+        num_removed
+    }
+}
+
+// Word `w` of `row` along with the same word shifted so each bit holds the
+// cell to its left and to its right.
+fn shifted(row: &[u64], w: usize) -> [u64; 3] {
+    let word = row[w];
+    let prev = if w > 0 { row[w - 1] } else { 0 };
+    let next = row.get(w + 1).copied().unwrap_or(0);
+    [(word << 1) | (prev >> 63), word, (word >> 1) | (next << 63)]
+}
+
+#[aoc(day4, part1)]
+fn part1(input: &str) -> usize {
+    BitGrid::new(input).count_accessible()
+}
+
+#[aoc(day4, part1, rules)]
+fn part1_rules(input: &str) -> usize {
+    count_accessible(input, &Rules::default())
+}
+
 #[aoc(day4, part2)]
 fn part2(input: &str) -> usize {
+    BitGrid::new(input).remove_all_accessible()
+}
+
+#[aoc(day4, part2, worklist)]
+fn part2_worklist(input: &str) -> usize {
+    count_removable(input, &Rules::default())
+}
+
+/// Same as `count_removable` with the default rules, but rescans the whole
+/// grid for accessible rolls after every round of removals.
+pub fn count_removable_rescan(input: &str) -> usize {
+    let rules = Rules::default();
     let mut grid = Grid::new(input);
     let initial_num_rolls = grid.data.iter().filter(|&&b| b).count();
     loop {
@@ -67,7 +467,7 @@ fn part2(input: &str) -> usize {
             .enumerate()
             .filter(|&(_, occupied)| occupied)
             .filter(|&(idx, _)| {
-                grid.neighbors(idx)
+                grid.neighbors(idx, &rules.adjacency)
                     .filter(|&neighbor_idx| grid.data[neighbor_idx])
                     .count()
                     < 4
@@ -86,3 +486,341 @@ fn part2(input: &str) -> usize {
     let num_rolls = grid.data.iter().filter(|&&b| b).count();
     initial_num_rolls - num_rolls
 }
+
+#[aoc(day4, part2, rescan)]
+fn part2_rescan(input: &str) -> usize {
+    count_removable_rescan(input)
+}
+
+/// The round in which each roll is removed when every roll accessible under
+/// some `Rules` is removed at once each round. Rolls that are never removed,
+/// and cells without a roll, are None.
+#[derive(Debug, Clone)]
+pub struct RemovalRounds {
+    rolls: Grid,
+    rounds: Grid<Option<u32>>,
+}
+
+impl RemovalRounds {
+    pub fn new(input: &str, rules: &Rules) -> Self {
+        let rolls = Grid::new(input);
+        let mut neighbor_counts = neighbor_counts(&rolls, rules);
+        let mut rounds = Grid {
+            row_len: rolls.row_len,
+            data: vec![None; rolls.data.len()],
+        };
+
+        // Work through the rolls a round at a time. A roll becomes
+        // accessible in the round after the one that dropped its neighbor
+        // count below the threshold.
+        let mut round = 1;
+        let mut removing: Vec<usize> = (0..rolls.data.len())
+            .filter(|&idx| rolls.data[idx] && neighbor_counts[idx] < rules.threshold)
+            .collect();
+        while !removing.is_empty() {
+            for &idx in &removing {
+                rounds.data[idx] = Some(round);
+            }
+            let mut next_round = Vec::new();
+            for idx in removing {
+                for neighbor_idx in rolls.neighbors(idx, &rules.adjacency) {
+                    neighbor_counts[neighbor_idx] -= 1;
+                    if rolls.data[neighbor_idx]
+                        && rounds.data[neighbor_idx].is_none()
+                        && neighbor_counts[neighbor_idx] + 1 == rules.threshold
+                    {
+                        next_round.push(neighbor_idx);
+                    }
+                }
+            }
+            removing = next_round;
+            round += 1;
+        }
+        RemovalRounds { rolls, rounds }
+    }
+
+    pub fn rounds(&self) -> &Grid<Option<u32>> {
+        &self.rounds
+    }
+
+    pub fn num_rounds(&self) -> u32 {
+        self.rounds
+            .data
+            .iter()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The positions of the rolls that are never removed.
+    pub fn stable_core(&self) -> impl Iterator<Item = usize> {
+        (0..self.rolls.data.len())
+            .filter(|&idx| self.rolls.data[idx] && self.rounds.data[idx].is_none())
+    }
+
+    /// Render the grid with each removed roll as its round in base 36
+    /// (rounds past 35 as `+`), the stable core as `@` and empty cells as `.`.
+    pub fn to_ascii(&self) -> String {
+        let mut ascii = String::with_capacity(self.rolls.data.len() + self.rolls.col_len());
+        for (idx, (&roll, round)) in self.rolls.data.iter().zip(&self.rounds.data).enumerate() {
+            if idx > 0 && idx % self.rolls.row_len == 0 {
+                ascii.push('\n');
+            }
+            ascii.push(match (roll, round) {
+                (false, _) => '.',
+                (true, None) => '@',
+                (true, &Some(round)) => char::from_digit(round, 36).unwrap_or('+'),
+            });
+        }
+        ascii
+    }
+
+    /// Render the grid with one pixel per cell. Removed rolls shade from red
+    /// in the first round to blue in the last, the stable core is white and
+    /// empty cells are black.
+    pub fn to_image(&self) -> RgbImage {
+        let last_round = self.num_rounds().max(2);
+        RgbImage::from_fn(
+            self.rolls.row_len as u32,
+            self.rolls.col_len() as u32,
+            |x, y| {
+                let idx = y as usize * self.rolls.row_len + x as usize;
+                match (self.rolls.data[idx], self.rounds.data[idx]) {
+                    (false, _) => Rgb([0, 0, 0]),
+                    (true, None) => Rgb([255, 255, 255]),
+                    (true, Some(round)) => {
+                        let blue = 255 * (round - 1) / (last_round - 1);
+                        Rgb([(255 - blue) as u8, 64, blue as u8])
+                    }
+                }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Rng;
+
+    const EXAMPLE: &str = "\
+..@@.@@@@.
+@@@.@.@.@@
+@@@@@.@.@@
+@.@@@@..@.
+@@.@@@@.@@
+.@@@@@@@.@
+.@.@.@.@@@
+@.@@@.@@@@
+.@@@@@@@@.
+@.@.@@@.@.";
+
+    // A `row_len` x `col_len` grid with roughly `percent`% of the cells
+    // holding rolls.
+    fn generate_grid(rng: &mut Rng, row_len: usize, col_len: usize, percent: u64) -> String {
+        (0..col_len)
+            .map(|_| {
+                (0..row_len)
+                    .map(|_| if rng.below(100) < percent { '@' } else { '.' })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn example_removal_rounds() {
+        let rounds = RemovalRounds::new(EXAMPLE, &Rules::default());
+        let ascii = rounds.to_ascii();
+        assert_eq!(
+            ascii,
+            "\
+..11.1121.
+134.2.2.32
+24578.1.33
+2.69@@..2.
+13.@@@@.21
+.24@@@@@.2
+.2.@.@.@@3
+1.4@@.@@@4
+.23@@@@@5.
+1.1.@@@.1."
+        );
+        assert_eq!(rounds.num_rounds(), 9);
+        let core: Vec<usize> = rounds.stable_core().collect();
+        let core_in_ascii: Vec<usize> = ascii
+            .replace('\n', "")
+            .bytes()
+            .enumerate()
+            .filter(|&(_, b)| b == b'@')
+            .map(|(idx, _)| idx)
+            .collect();
+        assert_eq!(core, core_in_ascii);
+        assert_eq!(core.len(), EXAMPLE.matches('@').count() - 43);
+
+        let first_round = rounds
+            .rounds()
+            .cells()
+            .iter()
+            .filter(|&&round| round == Some(1));
+        assert_eq!(
+            first_round.count(),
+            count_accessible(EXAMPLE, &Rules::default())
+        );
+    }
+
+    // The sorted positions around `pos` in an empty `row_len` x `col_len`
+    // grid.
+    fn neighbors_of(
+        (row_len, col_len): (usize, usize),
+        pos: usize,
+        neighborhood: Neighborhood,
+        topology: Topology,
+    ) -> Vec<usize> {
+        let grid = Grid {
+            row_len,
+            data: vec![false; row_len * col_len],
+        };
+        let mut neighbors: Vec<usize> = grid
+            .neighbors(pos, &Adjacency::new(neighborhood, topology))
+            .collect();
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    #[test]
+    fn neighborhoods_on_bounded_grids() {
+        use {Neighborhood::*, Topology::Bounded};
+        assert_eq!(neighbors_of((3, 3), 4, VonNeumann, Bounded), [1, 3, 5, 7]);
+        assert_eq!(neighbors_of((3, 3), 0, VonNeumann, Bounded), [1, 3]);
+        assert_eq!(
+            neighbors_of((3, 3), 4, Moore, Bounded),
+            [0, 1, 2, 3, 5, 6, 7, 8]
+        );
+        assert_eq!(neighbors_of((3, 3), 0, Moore, Bounded), [1, 3, 4]);
+        assert_eq!(
+            neighbors_of((5, 5), 0, Radius(2), Bounded),
+            [1, 2, 5, 6, 7, 10, 11, 12]
+        );
+        assert_eq!(neighbors_of((5, 5), 12, Radius(2), Bounded).len(), 24);
+        // Odd rows are shifted right, so an even row reaches up and down to
+        // the left and an odd row to the right.
+        assert_eq!(
+            neighbors_of((4, 4), 9, HexOffset, Bounded),
+            [4, 5, 8, 10, 12, 13]
+        );
+        assert_eq!(
+            neighbors_of((4, 4), 5, HexOffset, Bounded),
+            [1, 2, 4, 6, 9, 10]
+        );
+        assert_eq!(neighbors_of((4, 4), 3, HexOffset, Bounded), [2, 6, 7]);
+    }
+
+    #[test]
+    fn neighborhoods_on_toroidal_grids() {
+        use {Neighborhood::*, Topology::Toroidal};
+        assert_eq!(neighbors_of((3, 3), 0, VonNeumann, Toroidal), [1, 2, 3, 6]);
+        assert_eq!(
+            neighbors_of((3, 3), 0, Moore, Toroidal),
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(neighbors_of((5, 5), 0, Radius(2), Toroidal).len(), 24);
+        assert_eq!(
+            neighbors_of((4, 4), 0, HexOffset, Toroidal),
+            [1, 3, 4, 7, 12, 15]
+        );
+    }
+
+    #[test]
+    fn topology_decides_edge_access() {
+        let full = "@@@\n@@@\n@@@";
+        let rules = |topology| Rules::new(Neighborhood::Moore, topology, 8);
+        // Only the center has all 8 neighbors unless the grid wraps.
+        assert_eq!(count_accessible(full, &rules(Topology::Bounded)), 8);
+        assert_eq!(count_accessible(full, &rules(Topology::Toroidal)), 0);
+        let von_neumann = Rules::new(Neighborhood::VonNeumann, Topology::Bounded, 3);
+        assert_eq!(count_accessible(full, &von_neumann), 4);
+    }
+
+    #[test]
+    #[should_panic = "hex neighborhoods only wrap around an even number of rows"]
+    fn hex_grids_wrap_only_even_rows() {
+        let rules = Rules::new(Neighborhood::HexOffset, Topology::Toroidal, 3);
+        count_accessible("@@\n@@\n@@", &rules);
+    }
+
+    #[test]
+    fn components_are_measured_largest_first() {
+        let grid = "\
+@@...@
+@@...@
+.....@
+@.@...
+.@....";
+        // A threshold of 0 leaves every roll in place.
+        let rules = Rules::new(Neighborhood::Moore, Topology::Bounded, 0);
+        assert_eq!(
+            remaining_components(grid, &rules),
+            [
+                Component {
+                    rolls: vec![0, 1, 6, 7],
+                    min: (0, 0),
+                    max: (1, 1),
+                    perimeter: 8,
+                },
+                Component {
+                    rolls: vec![5, 11, 17],
+                    min: (5, 0),
+                    max: (5, 2),
+                    perimeter: 8,
+                },
+                // Joined only through the corners of the middle roll.
+                Component {
+                    rolls: vec![18, 20, 25],
+                    min: (0, 3),
+                    max: (2, 4),
+                    perimeter: 12,
+                },
+            ]
+        );
+
+        let components = remaining_components(EXAMPLE, &Rules::default());
+        let sizes: Vec<usize> = components.iter().map(Component::size).collect();
+        assert!(sizes.is_sorted_by(|a, b| a >= b), "{sizes:?}");
+        assert_eq!(sizes.iter().sum::<usize>(), 28);
+    }
+
+    #[test]
+    fn removal_matches_rescan() {
+        let mut rng = Rng::new(35);
+        for _ in 0..500 {
+            let (row_len, col_len) = (rng.below(40) as usize + 1, rng.below(40) as usize + 1);
+            let percent = rng.below(101);
+            let grid = generate_grid(&mut rng, row_len, col_len, percent);
+            let expected = count_removable_rescan(&grid);
+            assert_eq!(
+                count_removable(&grid, &Rules::default()),
+                expected,
+                "{grid}"
+            );
+            assert_eq!(
+                BitGrid::new(&grid).remove_all_accessible(),
+                expected,
+                "{grid}"
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "slow outside release builds"]
+    fn removal_matches_rescan_on_large_grids() {
+        let mut rng = Rng::new(5000);
+        for percent in [60, 70, 80] {
+            let grid = generate_grid(&mut rng, 5000, 5000, percent);
+            let expected = count_removable_rescan(&grid);
+            assert_eq!(count_removable(&grid, &Rules::default()), expected);
+            assert_eq!(BitGrid::new(&grid).remove_all_accessible(), expected);
+        }
+    }
+}
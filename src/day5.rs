@@ -1,76 +1,980 @@
-use std::{cmp::Ordering, ops::Range};
+use std::{
+    fs::File,
+    io,
+    iter::Sum,
+    ops::{Range, Sub},
+    path::Path,
+};
 
 use aoc_runner_derive::aoc;
+use memmap2::Mmap;
 
-#[derive(Debug)]
-struct IntervalSet(Vec<Range<usize>>);
-impl IntervalSet {
-    fn new(mut ranges: Vec<Range<usize>>) -> Self {
-        ranges.sort_by_key(|r| r.start);
-
-        let mut merged_tail = 0;
-        for idx in 1..ranges.len() {
-            if ranges[idx].start >= ranges[merged_tail].end {
-                merged_tail += 1;
-                ranges[merged_tail] = ranges[idx].clone();
+use crate::ranges;
+
+/// A set of values stored as a sorted list of disjoint, non-adjacent
+/// half-open ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntervalSet<T>(Vec<Range<T>>);
+
+impl<T: Ord + Copy> Default for IntervalSet<T> {
+    fn default() -> Self {
+        IntervalSet(Vec::new())
+    }
+}
+
+impl<T: Ord + Copy> IntervalSet<T> {
+    pub fn new(mut ranges: Vec<Range<T>>) -> Self {
+        ranges::normalize(&mut ranges);
+        IntervalSet(ranges)
+    }
+
+    pub fn contains(&self, needle: T) -> bool {
+        // The first range that ends after the needle is the only one that
+        // could hold it.
+        let idx = self.0.partition_point(|range| range.end <= needle);
+        self.0.get(idx).is_some_and(|range| range.start <= needle)
+    }
+
+    pub fn ranges(&self) -> &[Range<T>] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Add every value in `range` to the set.
+    pub fn insert(&mut self, range: Range<T>) {
+        if range.start >= range.end {
+            return;
+        }
+        // Every range from the first that reaches `range` up to the first
+        // that starts beyond it merges into one.
+        let first = self.0.partition_point(|r| r.end < range.start);
+        let last = self.0.partition_point(|r| r.start <= range.end);
+        let merged = if first == last {
+            range
+        } else {
+            std::cmp::min(self.0[first].start, range.start)
+                ..std::cmp::max(self.0[last - 1].end, range.end)
+        };
+        self.0.splice(first..last, [merged]);
+    }
+
+    /// Take every value in `range` out of the set.
+    pub fn remove(&mut self, range: Range<T>) {
+        if range.start >= range.end {
+            return;
+        }
+        let first = self.0.partition_point(|r| r.end <= range.start);
+        let last = self.0.partition_point(|r| r.start < range.end);
+        if first == last {
+            return;
+        }
+        // Only the ranges at either end can stick out past `range`.
+        let head = self.0[first].start..range.start;
+        let tail = range.end..self.0[last - 1].end;
+        let remaining = [head, tail].into_iter().filter(|r| r.start < r.end);
+        self.0.splice(first..last, remaining);
+    }
+
+    /// For each of `queries`, the index of the range holding it, in the
+    /// order the queries were given.
+    ///
+    /// Rather than searching for each query, the queries are sorted and swept
+    /// against the ranges in a single merge pass.
+    pub fn locate_all(&self, queries: &[T]) -> Vec<Option<usize>> {
+        let mut order: Vec<usize> = (0..queries.len()).collect();
+        order.sort_unstable_by_key(|&idx| queries[idx]);
+
+        let mut located = vec![None; queries.len()];
+        let mut range_idx = 0;
+        for idx in order {
+            let query = queries[idx];
+            while self
+                .0
+                .get(range_idx)
+                .is_some_and(|range| range.end <= query)
+            {
+                range_idx += 1;
+            }
+            if self
+                .0
+                .get(range_idx)
+                .is_some_and(|range| range.start <= query)
+            {
+                located[idx] = Some(range_idx);
+            }
+        }
+        located
+    }
+
+    /// The ranges between the ranges of the set.
+    pub fn gaps(&self) -> impl Iterator<Item = Range<T>> + '_ {
+        self.0.windows(2).map(|pair| pair[0].end..pair[1].start)
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        IntervalSet::new(self.0.iter().chain(&other.0).cloned().collect())
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let (mut a, mut b) = (self.0.iter().peekable(), other.0.iter().peekable());
+        while let (Some(x), Some(y)) = (a.peek(), b.peek()) {
+            let start = std::cmp::max(x.start, y.start);
+            let end = std::cmp::min(x.end, y.end);
+            if start < end {
+                ranges.push(start..end);
+            }
+            // Whichever range ends first can't overlap anything else.
+            if x.end < y.end {
+                a.next();
             } else {
-                ranges[merged_tail].end = std::cmp::max(ranges[merged_tail].end, ranges[idx].end);
+                b.next();
             }
         }
-        ranges.truncate(merged_tail + 1);
         IntervalSet(ranges)
     }
 
-    fn contains(&self, needle: usize) -> bool {
-        self.0
-            .binary_search_by(
-                |range| match (range.start.cmp(&needle), range.end.cmp(&needle)) {
-                    (Ordering::Less | Ordering::Equal, Ordering::Greater) => Ordering::Equal,
-                    (Ordering::Greater, _) => Ordering::Greater,
-                    (_, Ordering::Less | Ordering::Equal) => Ordering::Less,
-                },
-            )
-            .is_ok()
+    /// The values of `self` that aren't in `other`.
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut result = self.clone();
+        for range in &other.0 {
+            result.remove(range.clone());
+        }
+        result
     }
 
-    fn ranges(&self) -> &[Range<usize>] {
-        &self.0
+    /// The values in exactly one of `self` and `other`.
+    pub fn symmetric_difference(&self, other: &Self) -> Self {
+        self.difference(other).union(&other.difference(self))
+    }
+
+    /// The values within `bounds` that aren't in the set.
+    pub fn complement(&self, bounds: Range<T>) -> Self {
+        IntervalSet::new(vec![bounds]).difference(self)
     }
 }
 
-#[aoc(day5, part1)]
-fn part1(input: &str) -> usize {
-    let mut lines = input.lines();
-    let fresh_ingredients: Vec<Range<usize>> = lines
-        .by_ref()
+impl<T: Ord + Copy + Sub<Output = T> + Sum> IntervalSet<T> {
+    /// The number of values in the set.
+    pub fn len(&self) -> T {
+        self.0.iter().map(|range| range.end - range.start).sum()
+    }
+}
+
+// The binary form of an `IntervalSet<u128>` is:
+//
+// - the magic bytes `IVS` and a version byte
+// - the number of ranges, as a little-endian u64
+// - the offset of each block of `BLOCK_LEN` ranges into the range data, as
+//   little-endian u64s
+// - the range data: for each range, the gap from the end of the previous range
+//   in its block (or from 0 for the first) and the length of the range, as
+//   LEB128 varints
+//
+// Restarting the deltas at each block lets a reader binary search the blocks by
+// their first start and decode only the block that could hold an ID.
+const MAGIC: &[u8; 3] = b"IVS";
+const VERSION: u8 = 1;
+const BLOCK_LEN: usize = 64;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 8;
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u128) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// Read a varint off the front of `bytes`.
+fn read_varint(bytes: &mut &[u8]) -> io::Result<u128> {
+    let mut value = 0;
+    for shift in (0..u128::BITS).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or_else(|| invalid_data("truncated varint"))?;
+        *bytes = rest;
+        let bits = u128::from(byte & 0x7f);
+        if bits.leading_zeros() < shift {
+            return Err(invalid_data("varint overflows u128"));
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("varint overflows u128"))
+}
+
+impl IntervalSet<u128> {
+    /// Serialize the set into its compact binary form.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut offsets = Vec::new();
+        let mut data = Vec::new();
+        for block in self.0.chunks(BLOCK_LEN) {
+            offsets.push(data.len() as u64);
+            let mut prev_end = 0;
+            for range in block {
+                write_varint(&mut data, range.start - prev_end);
+                write_varint(&mut data, range.end - range.start);
+                prev_end = range.end;
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + 8 * offsets.len() + data.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(self.0.len() as u64).to_le_bytes());
+        for offset in offsets {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+        }
+        bytes.extend_from_slice(&data);
+        bytes
+    }
+
+    /// Deserialize a set written by [`IntervalSet::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        Ok(IntervalSet(IntervalSetView::new(bytes)?.ranges().collect()))
+    }
+}
+
+/// A serialized `IntervalSet<u128>` that's queried in place.
+///
+/// The whole encoding is checked when the view is created, so queries only
+/// decode the one block that could hold an ID.
+#[derive(Debug, Clone, Copy)]
+pub struct IntervalSetView<'a> {
+    num_ranges: usize,
+    offsets: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a> IntervalSetView<'a> {
+    pub fn new(bytes: &'a [u8]) -> io::Result<Self> {
+        let view = Self::split(bytes)?;
+        view.validate()?;
+        Ok(view)
+    }
+
+    // Split the serialized set into its parts, checking only the header.
+    fn split(bytes: &'a [u8]) -> io::Result<Self> {
+        let header = bytes
+            .get(..HEADER_LEN)
+            .ok_or_else(|| invalid_data("truncated header"))?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not a serialized interval set"));
+        }
+        if header[MAGIC.len()] != VERSION {
+            return Err(invalid_data("unsupported interval set version"));
+        }
+        let num_ranges = u64::from_le_bytes(header[MAGIC.len() + 1..].try_into().unwrap());
+        let num_ranges =
+            usize::try_from(num_ranges).map_err(|_| invalid_data("too many ranges"))?;
+        let offsets_len = num_ranges.div_ceil(BLOCK_LEN) * 8;
+        if bytes.len() - HEADER_LEN < offsets_len {
+            return Err(invalid_data("truncated block offsets"));
+        }
+        let (offsets, data) = bytes[HEADER_LEN..].split_at(offsets_len);
+        Ok(IntervalSetView {
+            num_ranges,
+            offsets,
+            data,
+        })
+    }
+
+    // Decode every block, checking that the blocks sit where their offsets say
+    // and that the ranges are sorted, non-empty and non-adjacent.
+    fn validate(&self) -> io::Result<()> {
+        let mut pos = 0;
+        let mut prev_end = None;
+        for block_idx in 0..self.num_blocks() {
+            if self.block_offset(block_idx) != pos {
+                return Err(invalid_data("block offset doesn't match the data"));
+            }
+            let mut block = self.block(block_idx);
+            while let Some(range) = block.try_next()? {
+                if range.is_empty() || prev_end.is_some_and(|end| range.start <= end) {
+                    return Err(invalid_data("ranges aren't sorted and disjoint"));
+                }
+                prev_end = Some(range.end);
+            }
+            pos = self.data.len() - block.data.len();
+        }
+        if pos != self.data.len() {
+            return Err(invalid_data("trailing bytes after the last range"));
+        }
+        Ok(())
+    }
+
+    pub fn num_ranges(&self) -> usize {
+        self.num_ranges
+    }
+
+    pub fn contains(&self, needle: u128) -> bool {
+        // The last block starting at or before the needle is the only one that
+        // could hold it.
+        let (mut lo, mut hi) = (0, self.num_blocks());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.block(mid).next().unwrap().start <= needle {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo > 0
+            && self
+                .block(lo - 1)
+                .take_while(|range| range.start <= needle)
+                .any(|range| needle < range.end)
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = Range<u128>> + 'a {
+        let view = *self;
+        (0..self.num_blocks()).flat_map(move |block_idx| view.block(block_idx))
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.offsets.len() / 8
+    }
+
+    fn block_offset(&self, block_idx: usize) -> usize {
+        let offset = &self.offsets[block_idx * 8..][..8];
+        u64::from_le_bytes(offset.try_into().unwrap()) as usize
+    }
+
+    fn block(&self, block_idx: usize) -> BlockDecoder<'a> {
+        BlockDecoder {
+            data: self
+                .data
+                .get(self.block_offset(block_idx)..)
+                .unwrap_or_default(),
+            remaining: BLOCK_LEN.min(self.num_ranges - block_idx * BLOCK_LEN),
+            prev_end: 0,
+        }
+    }
+}
+
+// Decodes the ranges of one block of a serialized set.
+struct BlockDecoder<'a> {
+    data: &'a [u8],
+    remaining: usize,
+    prev_end: u128,
+}
+
+impl BlockDecoder<'_> {
+    fn try_next(&mut self) -> io::Result<Option<Range<u128>>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let overflow = || invalid_data("range overflows u128");
+        let start = self
+            .prev_end
+            .checked_add(read_varint(&mut self.data)?)
+            .ok_or_else(overflow)?;
+        let end = start
+            .checked_add(read_varint(&mut self.data)?)
+            .ok_or_else(overflow)?;
+        self.prev_end = end;
+        Ok(Some(start..end))
+    }
+}
+
+impl Iterator for BlockDecoder<'_> {
+    type Item = Range<u128>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next()
+            .expect("the view was validated when it was created")
+    }
+}
+
+/// A serialized `IntervalSet<u128>` queried straight from a memory-mapped
+/// file.
+#[derive(Debug)]
+pub struct MappedIntervalSet {
+    map: Mmap,
+}
+
+impl MappedIntervalSet {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the map is only ever read, and the file must not be changed
+        // while it's open, as with any memory-mapped file.
+        let map = unsafe { Mmap::map(&file)? };
+        IntervalSetView::new(&map)?;
+        Ok(MappedIntervalSet { map })
+    }
+
+    pub fn view(&self) -> IntervalSetView<'_> {
+        IntervalSetView::split(&self.map).expect("the file was validated when it was opened")
+    }
+
+    pub fn contains(&self, needle: u128) -> bool {
+        self.view().contains(needle)
+    }
+}
+
+/// A line of the fresh ingredient database, as an inclusive `start-end`
+/// range, an open-ended `start-` or `-end` range, or a single ID. Lines
+/// starting with `!` exclude their IDs instead.
+///
+/// Bounds are widened to `u128` so the range past an inclusive end of
+/// `usize::MAX` can still be written as a half-open range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeLine {
+    Fresh(Range<u128>),
+    Exclude(Range<u128>),
+}
+
+impl RangeLine {
+    fn parse(line: &str) -> Self {
+        let (exclude, spec) = match line.strip_prefix('!') {
+            Some(spec) => (true, spec),
+            None => (false, line),
+        };
+        let parse_id = |id: &str| id.parse::<usize>().unwrap() as u128;
+        let range = match spec.split_once('-') {
+            Some((start, end)) => {
+                let start = if start.is_empty() { 0 } else { parse_id(start) };
+                let end = if end.is_empty() {
+                    usize::MAX as u128
+                } else {
+                    parse_id(end)
+                };
+                start..end + 1
+            }
+            None => {
+                let id = parse_id(spec);
+                id..id + 1
+            }
+        };
+        if exclude {
+            RangeLine::Exclude(range)
+        } else {
+            RangeLine::Fresh(range)
+        }
+    }
+}
+
+// The range lines of the database along with the (1-based) line each came
+// from.
+fn parse_range_lines(input: &str) -> impl Iterator<Item = (usize, RangeLine)> + '_ {
+    input
+        .lines()
         .take_while(|line| !line.is_empty())
-        .map(|line| {
-            let (start, end) = line.split_once('-').unwrap();
-            start.parse().unwrap()..end.parse::<usize>().unwrap() + 1
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, RangeLine::parse(line)))
+}
+
+/// The IDs that are fresh according to the database: those in any fresh line
+/// that aren't in any exclusion line, wherever the lines appear.
+pub fn fresh_ingredients(input: &str) -> IntervalSet<u128> {
+    let (mut fresh, mut excluded) = (Vec::new(), Vec::new());
+    for (_, line) in parse_range_lines(input) {
+        match line {
+            RangeLine::Fresh(range) => fresh.push(range),
+            RangeLine::Exclude(range) => excluded.push(range),
+        }
+    }
+    IntervalSet::new(fresh).difference(&IntervalSet::new(excluded))
+}
+
+/// A fresh range as written in the database, along with the (1-based) line
+/// it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRange {
+    pub line: usize,
+    pub range: Range<u128>,
+}
+
+/// The fresh lines of the database before merging, indexed to find every
+/// range that covers an ingredient ID.
+///
+/// The ranges are sorted by start and treated as a balanced binary tree where
+/// the middle of each slice is the root of that slice. Each node also tracks
+/// the largest end in its subtree so whole subtrees that end before an ID can
+/// be skipped.
+#[derive(Debug, Clone)]
+pub struct FreshRangeIndex {
+    ranges: Vec<SourceRange>,
+    max_end: Vec<u128>,
+}
+
+impl FreshRangeIndex {
+    pub fn new(input: &str) -> Self {
+        let mut ranges: Vec<SourceRange> = parse_range_lines(input)
+            .filter_map(|(line, range_line)| match range_line {
+                // Inverted lines such as `10-5` cover nothing.
+                RangeLine::Fresh(range) if !range.is_empty() => Some(SourceRange { line, range }),
+                _ => None,
+            })
+            .collect();
+        ranges.sort_by_key(|source| source.range.start);
+        let mut max_end = vec![0; ranges.len()];
+        fill_max_end(&ranges, &mut max_end);
+        FreshRangeIndex { ranges, max_end }
+    }
+
+    /// Every original range that covers `id`, in order of their start.
+    pub fn covering(&self, id: usize) -> Vec<&SourceRange> {
+        let mut found = Vec::new();
+        self.collect_covering(0..self.ranges.len(), id as u128, &mut found);
+        found
+    }
+
+    fn collect_covering<'a>(
+        &'a self,
+        subtree: Range<usize>,
+        id: u128,
+        found: &mut Vec<&'a SourceRange>,
+    ) {
+        if subtree.is_empty() {
+            return;
+        }
+        let mid = subtree.start + subtree.len() / 2;
+        if self.max_end[mid] <= id {
+            return;
+        }
+        self.collect_covering(subtree.start..mid, id, found);
+        let source = &self.ranges[mid];
+        if source.range.start <= id {
+            if id < source.range.end {
+                found.push(source);
+            }
+            self.collect_covering(mid + 1..subtree.end, id, found);
+        }
+    }
+
+    /// The number of original ranges covering each part of the ID space from
+    /// the lowest start to the highest end, as consecutive ranges with the
+    /// number of original ranges that overlap there. Gaps have a count of 0.
+    pub fn coverage(&self) -> Vec<(Range<u128>, usize)> {
+        let mut events: Vec<(u128, isize)> = self
+            .ranges
+            .iter()
+            .flat_map(|source| [(source.range.start, 1), (source.range.end, -1)])
+            .collect();
+        events.sort_unstable();
+
+        let mut coverage: Vec<(Range<u128>, usize)> = Vec::new();
+        let mut count = 0usize;
+        for pair in events.windows(2) {
+            // Ends sort before starts at the same ID, and every range ends
+            // after it starts, so the count never drops below 0.
+            count = count.checked_add_signed(pair[0].1).unwrap();
+            let segment = pair[0].0..pair[1].0;
+            if segment.is_empty() {
+                continue;
+            }
+            match coverage.last_mut() {
+                Some((last, last_count)) if *last_count == count => last.end = segment.end,
+                _ => coverage.push((segment, count)),
+            }
+        }
+        coverage
+    }
+}
+
+// Fill in the largest end of the subtree rooted at the middle of `ranges`,
+// returning it.
+fn fill_max_end(ranges: &[SourceRange], max_end: &mut [u128]) -> u128 {
+    if ranges.is_empty() {
+        return 0;
+    }
+    let mid = ranges.len() / 2;
+    let (left_ranges, right_ranges) = (&ranges[..mid], &ranges[mid + 1..]);
+    let (left_max, rest) = max_end.split_at_mut(mid);
+    let (mid_max, right_max) = rest.split_first_mut().unwrap();
+    *mid_max = ranges[mid]
+        .range
+        .end
+        .max(fill_max_end(left_ranges, left_max))
+        .max(fill_max_end(right_ranges, right_max));
+    *mid_max
+}
+
+/// The result of checking a batch of ingredient IDs against the fresh
+/// ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngredientCheck {
+    /// Whether each ingredient is fresh, in the order given.
+    pub fresh: Vec<bool>,
+    /// The positions of the ingredients that are the first or last ID of a
+    /// merged fresh range.
+    pub on_boundary: Vec<usize>,
+}
+
+/// Check every ingredient in `ids` against `fresh` in a single sweep.
+pub fn check_ingredients(fresh: &IntervalSet<u128>, ids: &[usize]) -> IngredientCheck {
+    let wide_ids: Vec<u128> = ids.iter().map(|&id| id as u128).collect();
+    let located = fresh.locate_all(&wide_ids);
+    let on_boundary = located
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, &range_idx)| {
+            let range = &fresh.ranges()[range_idx?];
+            let id = wide_ids[idx];
+            (id == range.start || id == range.end - 1).then_some(idx)
         })
         .collect();
-    let fresh_ingredients = IntervalSet::new(fresh_ingredients);
+    IngredientCheck {
+        fresh: located.iter().map(Option::is_some).collect(),
+        on_boundary,
+    }
+}
 
-    let available_ingredients: Vec<usize> = lines.map(|line| line.parse().unwrap()).collect();
+fn parse_available_ingredients(input: &str) -> Vec<usize> {
+    input
+        .lines()
+        .skip_while(|line| !line.is_empty())
+        .skip(1)
+        .map(|line| line.parse().unwrap())
+        .collect()
+}
+
+#[aoc(day5, part1)]
+fn part1(input: &str) -> usize {
+    let fresh_ingredients = fresh_ingredients(input);
+    let available_ingredients = parse_available_ingredients(input);
+    check_ingredients(&fresh_ingredients, &available_ingredients)
+        .fresh
+        .into_iter()
+        .filter(|&fresh| fresh)
+        .count()
+}
+
+#[aoc(day5, part1, binary_search)]
+fn part1_binary_search(input: &str) -> usize {
+    let fresh_ingredients = fresh_ingredients(input);
+
+    let available_ingredients = parse_available_ingredients(input);
 
     available_ingredients
         .iter()
         .copied()
-        .filter(|&ingredient| fresh_ingredients.contains(ingredient))
+        .filter(|&ingredient| fresh_ingredients.contains(ingredient as u128))
         .count()
 }
 
 #[aoc(day5, part2)]
-fn part2(input: &str) -> usize {
-    let fresh_ranges: Vec<Range<usize>> = input
-        .lines()
-        .take_while(|line| !line.is_empty())
-        .map(|line| {
-            let (start, end) = line.split_once('-').unwrap();
-            start.parse().unwrap()..end.parse::<usize>().unwrap() + 1
-        })
-        .collect();
-    let intervalset = IntervalSet::new(fresh_ranges);
+fn part2(input: &str) -> u128 {
+    fresh_ingredients(input).len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Rng;
 
-    intervalset.ranges().iter().map(|range| range.len()).sum()
+    // A set of `num_ranges` random ranges whose bounds start at `base`, with
+    // gaps and lengths of up to `max_step` so bounds can run up to near
+    // `u128::MAX`.
+    fn random_set(
+        rng: &mut Rng,
+        num_ranges: usize,
+        base: u128,
+        max_step: u64,
+    ) -> IntervalSet<u128> {
+        let mut ranges = Vec::new();
+        let mut end = base;
+        for _ in 0..num_ranges {
+            let start = end + 1 + u128::from(rng.below(max_step));
+            end = start + 1 + u128::from(rng.below(max_step));
+            ranges.push(start..end);
+        }
+        IntervalSet::new(ranges)
+    }
+
+    fn sample_sets() -> Vec<IntervalSet<u128>> {
+        let mut rng = Rng::new(5);
+        vec![
+            IntervalSet::default(),
+            IntervalSet::new(vec![0..1]),
+            IntervalSet::new(vec![0..u128::MAX]),
+            IntervalSet::new(vec![0..1, u128::MAX - 1..u128::MAX]),
+            random_set(&mut rng, 10, 0, 100),
+            random_set(&mut rng, BLOCK_LEN, 0, 100),
+            random_set(&mut rng, BLOCK_LEN + 1, 0, 100),
+            random_set(&mut rng, 5 * BLOCK_LEN + 3, 0, 1 << 40),
+            random_set(&mut rng, 3 * BLOCK_LEN, u128::MAX - (1 << 40), 1000),
+        ]
+    }
+
+    // Several blocks of ranges, for corrupting.
+    fn sample_bytes() -> Vec<u8> {
+        random_set(&mut Rng::new(44), 3 * BLOCK_LEN + 7, 0, 1000).to_bytes()
+    }
+
+    fn assert_invalid(bytes: &[u8]) {
+        let err = IntervalSet::from_bytes(bytes).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{err}");
+    }
+
+    // The values of a set of IDs below 64, one bit each.
+    fn bits(set: &IntervalSet<u32>) -> u64 {
+        set.ranges()
+            .iter()
+            .map(range_bits)
+            .fold(0, |bits, range| bits | range)
+    }
+
+    fn range_bits(range: &Range<u32>) -> u64 {
+        range.clone().fold(0, |bits, id| bits | 1 << id)
+    }
+
+    // Up to 5 ranges within `0..64`, some of them empty.
+    fn random_ranges(rng: &mut Rng) -> Vec<Range<u32>> {
+        (0..rng.below(6))
+            .map(|_| {
+                let start = rng.below(64) as u32;
+                start..start + rng.below(65 - u64::from(start)).min(12) as u32
+            })
+            .collect()
+    }
+
+    fn assert_normalized(set: &IntervalSet<u32>) {
+        let ranges = set.ranges();
+        assert!(
+            ranges.iter().all(|range| range.start < range.end),
+            "{ranges:?}"
+        );
+        assert!(
+            ranges.windows(2).all(|pair| pair[0].end < pair[1].start),
+            "{ranges:?}"
+        );
+    }
+
+    #[test]
+    fn set_operations_match_bitsets() {
+        let mut rng = Rng::new(40);
+        for _ in 0..2000 {
+            let (a_ranges, b_ranges) = (random_ranges(&mut rng), random_ranges(&mut rng));
+            let a = IntervalSet::new(a_ranges.clone());
+            let b = IntervalSet::new(b_ranges.clone());
+            let (a_bits, b_bits) = (bits(&a), bits(&b));
+            assert_eq!(
+                a_bits,
+                a_ranges.iter().map(range_bits).fold(0, |x, y| x | y)
+            );
+            assert_eq!(a.len(), a_bits.count_ones());
+            assert_eq!(a.is_empty(), a_bits == 0);
+
+            let results = [
+                (a.union(&b), a_bits | b_bits),
+                (a.intersection(&b), a_bits & b_bits),
+                (a.difference(&b), a_bits & !b_bits),
+                (a.symmetric_difference(&b), a_bits ^ b_bits),
+            ];
+            for (result, expected) in results {
+                assert_normalized(&result);
+                assert_eq!(bits(&result), expected, "{a:?} {b:?}");
+            }
+
+            for range in &b_ranges {
+                let mut inserted = a.clone();
+                inserted.insert(range.clone());
+                assert_normalized(&inserted);
+                assert_eq!(
+                    bits(&inserted),
+                    a_bits | range_bits(range),
+                    "{a:?} + {range:?}"
+                );
+                let mut removed = a.clone();
+                removed.remove(range.clone());
+                assert_normalized(&removed);
+                assert_eq!(
+                    bits(&removed),
+                    a_bits & !range_bits(range),
+                    "{a:?} - {range:?}"
+                );
+                let complement = a.complement(range.clone());
+                assert_normalized(&complement);
+                assert_eq!(
+                    bits(&complement),
+                    range_bits(range) & !a_bits,
+                    "{range:?} - {a:?}"
+                );
+            }
+
+            let gaps: Vec<Range<u32>> = a.gaps().collect();
+            assert_eq!(gaps.len(), a.ranges().len().saturating_sub(1));
+            if let (Some(first), Some(last)) = (a.ranges().first(), a.ranges().last()) {
+                let hull = range_bits(&(first.start..last.end));
+                let gap_bits = gaps.iter().map(range_bits).fold(0, |x, y| x | y);
+                assert!(gaps.iter().all(|gap| gap.start < gap.end));
+                assert_eq!(gap_bits, hull & !a_bits);
+            }
+
+            let queries: Vec<u32> = (0..20).map(|_| rng.below(64) as u32).collect();
+            for (&query, located) in queries.iter().zip(a.locate_all(&queries)) {
+                assert_eq!(a.contains(query), a_bits & 1 << query != 0);
+                match located {
+                    Some(idx) => assert!(a.ranges()[idx].contains(&query)),
+                    None => assert!(!a.contains(query)),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        for set in sample_sets() {
+            let bytes = set.to_bytes();
+            assert_eq!(IntervalSet::from_bytes(&bytes).unwrap(), set);
+            assert_eq!(
+                IntervalSetView::new(&bytes).unwrap().num_ranges(),
+                set.ranges().len()
+            );
+        }
+    }
+
+    #[test]
+    fn view_contains_matches_set() {
+        for set in sample_sets() {
+            let bytes = set.to_bytes();
+            let view = IntervalSetView::new(&bytes).unwrap();
+            for range in set.ranges() {
+                let needles = [
+                    range.start.checked_sub(1),
+                    Some(range.start),
+                    Some(range.end - 1),
+                    Some(range.end),
+                ];
+                for needle in needles.into_iter().flatten() {
+                    assert_eq!(view.contains(needle), set.contains(needle), "{needle}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_magic_is_rejected() {
+        let mut bytes = sample_bytes();
+        bytes[0] = b'X';
+        assert_invalid(&bytes);
+    }
+
+    #[test]
+    fn wrong_version_is_rejected() {
+        let mut bytes = sample_bytes();
+        bytes[MAGIC.len()] = VERSION + 1;
+        assert_invalid(&bytes);
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        let bytes = sample_bytes();
+        for len in 0..bytes.len() {
+            assert_invalid(&bytes[..len]);
+        }
+    }
+
+    #[test]
+    fn bad_block_offset_is_rejected() {
+        let bytes = sample_bytes();
+        for block_idx in 0..4 {
+            let offset = HEADER_LEN + 8 * block_idx;
+            for delta in [1, u64::MAX] {
+                let mut bytes = bytes.clone();
+                let value = u64::from_le_bytes(bytes[offset..][..8].try_into().unwrap());
+                bytes[offset..][..8].copy_from_slice(&value.wrapping_add(delta).to_le_bytes());
+                assert_invalid(&bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn trailing_bytes_are_rejected() {
+        let mut bytes = sample_bytes();
+        bytes.push(0);
+        assert_invalid(&bytes);
+    }
+
+    #[test]
+    fn mapped_set_reads_a_file() {
+        let set = random_set(&mut Rng::new(7), 2 * BLOCK_LEN + 5, 0, 1000);
+        let mut trailing = set.to_bytes();
+        trailing.push(0);
+        // The mapped file mustn't change while it's open, so each set gets its
+        // own file.
+        let open = |name: &str, bytes: &[u8]| {
+            let path = std::env::temp_dir().join(format!("day5-{name}-{}", std::process::id()));
+            std::fs::write(&path, bytes).unwrap();
+            let mapped = MappedIntervalSet::open(&path);
+            std::fs::remove_file(&path).unwrap();
+            mapped
+        };
+        let mapped = open("valid", &set.to_bytes());
+        let bad_mapped = open("trailing", &trailing);
+
+        let mapped = mapped.unwrap();
+        assert_eq!(mapped.view().num_ranges(), set.ranges().len());
+        assert!(mapped.view().ranges().eq(set.ranges().iter().cloned()));
+        for range in set.ranges() {
+            assert!(mapped.contains(range.start) && mapped.contains(range.end - 1));
+            assert!(!mapped.contains(range.end));
+        }
+        assert_eq!(bad_mapped.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn every_line_form_is_parsed() {
+        let max = usize::MAX as u128;
+        let lines = [
+            ("10-40", RangeLine::Fresh(10..41)),
+            ("500-", RangeLine::Fresh(500..max + 1)),
+            ("-20", RangeLine::Fresh(0..21)),
+            ("30", RangeLine::Fresh(30..31)),
+            ("!15-17", RangeLine::Exclude(15..18)),
+            ("!600-1000", RangeLine::Exclude(600..1001)),
+            ("!35", RangeLine::Exclude(35..36)),
+            (
+                &format!("{}-{max}", max - 1),
+                RangeLine::Fresh(max - 1..max + 1),
+            ),
+            (&format!("{max}"), RangeLine::Fresh(max..max + 1)),
+        ];
+        for (line, expected) in &lines {
+            assert_eq!(&RangeLine::parse(line), expected, "{line}");
+        }
+
+        let input: Vec<&str> = lines.iter().map(|&(line, _)| line).collect();
+        let fresh = fresh_ingredients(&input.join("\n"));
+        assert_eq!(
+            fresh.ranges(),
+            [0..15, 18..35, 36..41, 500..600, 1001..max + 1]
+        );
+        assert_eq!(fresh.len(), 15 + 17 + 5 + 100 + (max + 1 - 1001));
+        assert!(fresh.contains(max) && !fresh.contains(max + 1));
+    }
+
+    #[test]
+    fn ingredients_are_checked_in_order() {
+        // The first two ranges merge into 3..9, so 5 and 6 are inside it.
+        let fresh = IntervalSet::new(vec![3..6, 6..9, 20..21]);
+        let ids = [8, 1, 3, 20, 6, 5, 9, 8, 21];
+        let check = check_ingredients(&fresh, &ids);
+        assert_eq!(
+            check.fresh,
+            [true, false, true, true, true, true, false, true, false]
+        );
+        assert_eq!(check.on_boundary, [0, 2, 3, 7]);
+    }
+
+    #[test]
+    fn inverted_lines_cover_nothing() {
+        let index = FreshRangeIndex::new("10-5\n1-20\n\n1");
+        assert_eq!(index.coverage(), [(1..21, 1)]);
+        let covering: Vec<usize> = index.covering(7).iter().map(|source| source.line).collect();
+        assert_eq!(covering, [2]);
+    }
 }
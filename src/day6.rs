@@ -1,75 +1,548 @@
+use std::{fmt, ops::Range};
+
 use aoc_runner_derive::aoc;
 
-#[aoc(day6, part1)]
-fn part1(input: &str) -> usize {
-    let mut lines = input.lines();
-    let mut ops_line = "";
-    let numbers: Vec<usize> = lines
-        .by_ref()
-        .take_while(|&line| {
-            let is_num = line.as_bytes()[0].is_ascii_digit();
-            if !is_num {
-                ops_line = line;
-            }
-            is_num
+/// An operator from the last row of the worksheet.
+///
+/// Operators apply to a problem's operands in reading order. Subtraction and
+/// division fold from the left, so `a - b - c` is `(a - b) - c`, while `^`
+/// folds from the right, so `a ^ b ^ c` is `a ^ (b ^ c)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Min,
+    Max,
+    Power,
+}
+
+impl Operator {
+    fn parse(op: &str) -> Self {
+        match op {
+            "+" => Operator::Add,
+            "-" => Operator::Subtract,
+            "*" => Operator::Multiply,
+            "/" => Operator::Divide,
+            "min" => Operator::Min,
+            "max" => Operator::Max,
+            "^" => Operator::Power,
+            op => panic!("invalid op: {op}"),
+        }
+    }
+
+    fn apply(self, operands: &[usize]) -> Result<i128, EvalError> {
+        let mut operands = operands.iter().map(|&operand| operand as i128);
+        let init = match self {
+            Operator::Power => operands.next_back(),
+            _ => operands.next(),
+        };
+        let init = init.ok_or(EvalError::NoOperands)?;
+        match self {
+            Operator::Add => operands.try_fold(init, |accum, x| {
+                accum.checked_add(x).ok_or(EvalError::Overflow)
+            }),
+            Operator::Subtract => operands.try_fold(init, |accum, x| {
+                accum.checked_sub(x).ok_or(EvalError::Overflow)
+            }),
+            Operator::Multiply => operands.try_fold(init, |accum, x| {
+                accum.checked_mul(x).ok_or(EvalError::Overflow)
+            }),
+            Operator::Divide => operands.try_fold(init, |accum, x| {
+                if x == 0 || accum % x != 0 {
+                    Err(EvalError::NotDivisible {
+                        dividend: accum,
+                        divisor: x,
+                    })
+                } else {
+                    Ok(accum / x)
+                }
+            }),
+            Operator::Min => Ok(operands.fold(init, i128::min)),
+            Operator::Max => Ok(operands.fold(init, i128::max)),
+            Operator::Power => operands.try_rfold(init, |exponent, base| {
+                u32::try_from(exponent)
+                    .ok()
+                    .and_then(|exponent| base.checked_pow(exponent))
+                    .ok_or(EvalError::Overflow)
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Min => "min",
+            Operator::Max => "max",
+            Operator::Power => "^",
         })
-        .flat_map(|line| line.split_whitespace())
-        .map(|num| num.parse().unwrap())
-        .collect();
-    let ops: Vec<&str> = ops_line.split_whitespace().collect();
-    let num_cols = ops.len();
-    (0..num_cols)
-        .map(|colidx| -> usize {
-            let col_nums = numbers.iter().copied().skip(colidx).step_by(num_cols);
-            match ops[colidx] {
-                "+" => col_nums.sum(),
-                "*" => col_nums.product(),
-                op => panic!("invalid op: {op}"),
+    }
+}
+
+/// Why a problem has no answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalError {
+    /// A division by zero, or one that would leave a remainder.
+    NotDivisible { dividend: i128, divisor: i128 },
+    /// An intermediate result that doesn't fit in an `i128`.
+    Overflow,
+    /// A problem with an operator but no digits to apply it to.
+    NoOperands,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::NotDivisible { dividend, divisor } => {
+                write!(f, "{dividend} is not divisible by {divisor}")
             }
-        })
-        .sum()
+            EvalError::Overflow => f.write_str("overflow"),
+            EvalError::NoOperands => f.write_str("no operands"),
+        }
+    }
 }
 
-#[aoc(day6, part2)]
-fn part2(input: &str) -> usize {
-    let mut input = input.as_bytes().to_vec();
-    input.push(b'\n');
-    let row_len = input.iter().position(|&b| b == b'\n').unwrap() + 1;
-    let num_rows = input.len() / row_len;
-    let op_row = &input[(num_rows - 1) * row_len..];
-    assert_eq!((input.len()) % row_len, 0);
-
-    let op_cols: Vec<_> = op_row
-        .iter()
-        .copied()
-        .enumerate()
-        .filter(|&(_idx, val)| val == b'\n' || !val.is_ascii_whitespace())
-        .collect();
-    let mut op_cols: Vec<_> = op_cols
-        .windows(2)
-        .map(|pair| {
-            let col_range = pair[0].0..pair[1].0 - 1;
-            let op = pair[0].1;
-            (col_range, op)
-        })
-        .collect();
-    op_cols.last_mut().unwrap().0.end += 1; // The last column doesn't have a trailing space
-
-    op_cols
-        .iter()
-        .map(|(col_range, op)| {
-            let numbers = col_range.clone().map(|colidx| {
-                (0..num_rows - 1)
-                    .map(|rowidx| input[rowidx * row_len + colidx])
-                    .filter(|b| b.is_ascii_digit())
-                    .map(|b| b - b'0')
-                    .fold(0usize, |accum, num| accum * 10 + num as usize)
-            });
-            match op {
-                b'+' => numbers.sum::<usize>(),
-                b'*' => numbers.product::<usize>(),
-                _ => panic!("invalid op"),
+impl std::error::Error for EvalError {}
+
+/// The order the digits of a problem are read in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reading {
+    /// Each row of the problem is a number.
+    Rows,
+    /// Each column of the problem is a number, read top to bottom, with the
+    /// rightmost column first.
+    Columns,
+}
+
+impl Reading {
+    pub fn other(self) -> Self {
+        match self {
+            Reading::Rows => Reading::Columns,
+            Reading::Columns => Reading::Rows,
+        }
+    }
+}
+
+// The number rows of a problem laid out so `reading` gives `operands`, each
+// as wide as the problem.
+fn lay_out(operator: Operator, operands: &[usize], reading: Reading) -> Vec<String> {
+    let op_len = operator.to_string().len();
+    let numbers: Vec<String> = operands.iter().map(usize::to_string).collect();
+    match reading {
+        Reading::Rows => {
+            let width = numbers
+                .iter()
+                .map(String::len)
+                .max()
+                .unwrap_or(0)
+                .max(op_len);
+            numbers
+                .iter()
+                .map(|number| format!("{number:>width$}"))
+                .collect()
+        }
+        Reading::Columns => {
+            // The first operand is the rightmost column, and each number runs
+            // down from the top row.
+            let width = numbers.len().max(op_len);
+            let height = numbers.iter().map(String::len).max().unwrap_or(0);
+            (0..height)
+                .map(|rowidx| {
+                    (0..width)
+                        .map(|colidx| {
+                            numbers
+                                .get(width - 1 - colidx)
+                                .and_then(|number| number.as_bytes().get(rowidx))
+                                .map_or(' ', |&b| b as char)
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    }
+}
+
+/// One problem of the worksheet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// The columns of the worksheet the problem covers.
+    pub column_span: Range<usize>,
+    pub operator: Operator,
+    /// The problem's part of each number row, padding included.
+    pub cells: Vec<String>,
+}
+
+impl Problem {
+    /// The numbers the operator applies to under `reading`.
+    pub fn operands(&self, reading: Reading) -> Vec<usize> {
+        match reading {
+            // Rows or columns with no digits, such as those under a wide
+            // operator, aren't operands.
+            Reading::Rows => self
+                .cells
+                .iter()
+                .map(|cell| cell.trim())
+                .filter(|cell| !cell.is_empty())
+                .map(|cell| cell.parse().unwrap())
+                .collect(),
+            Reading::Columns => (0..self.column_span.len())
+                .rev()
+                .filter_map(|colidx| {
+                    let digits = self
+                        .cells
+                        .iter()
+                        .map(|cell| cell.as_bytes()[colidx])
+                        .filter(|b| b.is_ascii_digit());
+                    digits.fold(None, |accum, b| {
+                        Some(accum.unwrap_or(0) * 10 + (b - b'0') as usize)
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub fn solve(&self, reading: Reading) -> Result<i128, EvalError> {
+        self.operator.apply(&self.operands(reading))
+    }
+}
+
+/// The worksheet, split into problems with an operator on its last row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Worksheet {
+    problems: Vec<Problem>,
+}
+
+impl Worksheet {
+    /// Lines may be of different lengths, with any missing columns treated
+    /// as blank. Problems are the runs of columns between fully blank ones.
+    pub fn new(input: &str) -> Self {
+        let lines: Vec<&[u8]> = input.lines().map(str::as_bytes).collect();
+        let (op_row, number_rows) = lines.split_last().unwrap();
+        let width = lines.iter().map(|line| line.len()).max().unwrap();
+        let cell = |line: &[u8], span: Range<usize>| -> String {
+            span.map(|colidx| *line.get(colidx).unwrap_or(&b' ') as char)
+                .collect()
+        };
+        let is_blank = |colidx: usize| {
+            lines
+                .iter()
+                .all(|line| line.get(colidx).is_none_or(u8::is_ascii_whitespace))
+        };
+
+        let mut spans = Vec::new();
+        let mut colidx = 0;
+        while colidx < width {
+            if is_blank(colidx) {
+                colidx += 1;
+                continue;
+            }
+            let start = colidx;
+            while colidx < width && !is_blank(colidx) {
+                colidx += 1;
             }
+            spans.push(start..colidx);
+        }
+
+        let problems = spans
+            .into_iter()
+            .map(|span| {
+                let op = cell(op_row, span.clone());
+                let op = op.trim();
+                assert!(
+                    !op.is_empty() && !op.contains(char::is_whitespace),
+                    "problem at columns {span:?} needs exactly one operator, found {op:?}"
+                );
+                Problem {
+                    operator: Operator::parse(op),
+                    cells: number_rows
+                        .iter()
+                        .map(|row| cell(row, span.clone()))
+                        .collect(),
+                    column_span: span,
+                }
+            })
+            .collect();
+        Worksheet { problems }
+    }
+
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    /// The sum of the answers to every problem under `reading`, or the first
+    /// problem that has no answer.
+    pub fn grand_total(&self, reading: Reading) -> Result<i128, EvalError> {
+        self.problems.iter().try_fold(0i128, |total, problem| {
+            total
+                .checked_add(problem.solve(reading)?)
+                .ok_or(EvalError::Overflow)
         })
-        .sum()
+    }
+
+    /// The same problems laid out so the other reading gives the operands
+    /// that `reading` gives here, so their grand totals match. Transposing
+    /// the result with the other reading gives back the original operands.
+    pub fn transposed(&self, reading: Reading) -> Worksheet {
+        let blocks: Vec<(Operator, Vec<String>)> = self
+            .problems
+            .iter()
+            .map(|problem| {
+                let operands = problem.operands(reading);
+                let rows = lay_out(problem.operator, &operands, reading.other());
+                (problem.operator, rows)
+            })
+            .collect();
+        let num_rows = blocks.iter().map(|(_, rows)| rows.len()).max().unwrap_or(0);
+
+        let mut text = String::new();
+        for rowidx in 0..num_rows {
+            let cells: Vec<String> = blocks
+                .iter()
+                .map(|(_, rows)| {
+                    let width = rows[0].len();
+                    let cell = rows.get(rowidx).map_or("", String::as_str);
+                    format!("{cell:width$}")
+                })
+                .collect();
+            text += &cells.join(" ");
+            text.push('\n');
+        }
+        let ops: Vec<String> = blocks
+            .iter()
+            .map(|(operator, rows)| {
+                format!("{:width$}", operator.to_string(), width = rows[0].len())
+            })
+            .collect();
+        text += &ops.join(" ");
+        Worksheet::new(&text)
+    }
+
+    /// Every problem's operands and answer under `reading`.
+    pub fn breakdown(&self, reading: Reading) -> Breakdown {
+        let problems = self
+            .problems
+            .iter()
+            .map(|problem| {
+                let operands = problem.operands(reading);
+                ProblemResult {
+                    column_span: problem.column_span.clone(),
+                    operator: problem.operator,
+                    result: problem.operator.apply(&operands),
+                    operands,
+                }
+            })
+            .collect();
+        Breakdown { problems }
+    }
+}
+
+/// Displays as the worksheet's text, with each line padded to the end of its
+/// last problem.
+impl fmt::Display for Worksheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let num_rows = self
+            .problems
+            .first()
+            .map_or(0, |problem| problem.cells.len());
+        let mut lines = vec![String::new(); num_rows + 1];
+        for problem in &self.problems {
+            let width = problem.column_span.end;
+            let (op_line, number_lines) = lines.split_last_mut().unwrap();
+            for (line, cell) in number_lines.iter_mut().zip(&problem.cells) {
+                *line = format!("{line:<start$}{cell}", start = problem.column_span.start);
+            }
+            *op_line = format!(
+                "{op_line:<start$}{:<len$}",
+                problem.operator.to_string(),
+                start = problem.column_span.start,
+                len = width - problem.column_span.start,
+            );
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProblemResult {
+    pub column_span: Range<usize>,
+    pub operator: Operator,
+    pub operands: Vec<usize>,
+    pub result: Result<i128, EvalError>,
+}
+
+/// The answer to each problem of a worksheet; `to_csv` gives one row per
+/// problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakdown {
+    pub problems: Vec<ProblemResult>,
+}
+
+impl Breakdown {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("first_column,last_column,operator,operands,result,error\n");
+        for problem in &self.problems {
+            let operands: Vec<String> = problem.operands.iter().map(usize::to_string).collect();
+            let (result, error) = match problem.result {
+                Ok(result) => (result.to_string(), String::new()),
+                Err(err) => (String::new(), err.to_string()),
+            };
+            csv += &format!(
+                "{},{},{},{},{result},{error}\n",
+                problem.column_span.start,
+                problem.column_span.end - 1,
+                problem.operator,
+                operands.join(" "),
+            );
+        }
+        csv
+    }
+}
+
+#[aoc(day6, part1)]
+fn part1(input: &str) -> i128 {
+    Worksheet::new(input).grand_total(Reading::Rows).unwrap()
+}
+
+#[aoc(day6, part2)]
+fn part2(input: &str) -> i128 {
+    Worksheet::new(input).grand_total(Reading::Columns).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every line padded to the full width, as in the puzzle.
+    const EXAMPLE: &str = concat!(
+        "123 328  51 64 \n",
+        " 45 64  387 23 \n",
+        "  6 98  215 314\n",
+        "*   +   *   +  ",
+    );
+
+    fn all_operands(worksheet: &Worksheet, reading: Reading) -> Vec<Vec<usize>> {
+        worksheet
+            .problems()
+            .iter()
+            .map(|problem| problem.operands(reading))
+            .collect()
+    }
+
+    #[test]
+    fn example_grand_totals() {
+        let worksheet = Worksheet::new(EXAMPLE);
+        assert_eq!(worksheet.grand_total(Reading::Rows), Ok(4277556));
+        assert_eq!(worksheet.grand_total(Reading::Columns), Ok(3263827));
+    }
+
+    #[test]
+    fn ragged_lines_are_padded() {
+        let trimmed: String = EXAMPLE
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_ne!(trimmed, EXAMPLE);
+        let worksheet = Worksheet::new(EXAMPLE);
+        let trimmed = Worksheet::new(&trimmed);
+        assert_eq!(trimmed.problems(), worksheet.problems());
+        let spans: Vec<Range<usize>> = worksheet
+            .problems()
+            .iter()
+            .map(|problem| problem.column_span.clone())
+            .collect();
+        assert_eq!(spans, [0..3, 4..7, 8..11, 12..15]);
+        for reading in [Reading::Rows, Reading::Columns] {
+            assert_eq!(trimmed.grand_total(reading), worksheet.grand_total(reading));
+        }
+    }
+
+    #[test]
+    fn breakdown_applies_each_operator() {
+        let worksheet = Worksheet::new(concat!(
+            "8 20 3 12   7   5 2 10\n",
+            "2  5 4  3   9   1 3  4\n",
+            "1  3 2  2   4   9 2\n",
+            "+ -  * /  min max ^ /",
+        ));
+        let breakdown = worksheet.breakdown(Reading::Rows);
+        let results: Vec<_> = breakdown
+            .problems
+            .iter()
+            .map(|problem| problem.result)
+            .collect();
+        assert_eq!(
+            results,
+            [
+                Ok(11),
+                Ok(12),
+                Ok(24),
+                Ok(2),
+                Ok(4),
+                Ok(9),
+                Ok(512),
+                Err(EvalError::NotDivisible {
+                    dividend: 10,
+                    divisor: 4
+                }),
+            ]
+        );
+        assert_eq!(
+            breakdown.to_csv(),
+            "\
+first_column,last_column,operator,operands,result,error
+0,0,+,8 2 1,11,
+2,3,-,20 5 3,12,
+5,5,*,3 4 2,24,
+7,8,/,12 3 2,2,
+10,12,min,7 9 4,4,
+14,16,max,5 1 9,9,
+18,18,^,2 3 2,512,
+20,21,/,10 4,,10 is not divisible by 4
+"
+        );
+    }
+
+    #[test]
+    fn operator_without_digits_has_no_answer() {
+        let worksheet = Worksheet::new("1  \n+ +");
+        for reading in [Reading::Rows, Reading::Columns] {
+            assert_eq!(worksheet.grand_total(reading), Err(EvalError::NoOperands));
+            let results: Vec<_> = worksheet
+                .breakdown(reading)
+                .problems
+                .iter()
+                .map(|problem| problem.result)
+                .collect();
+            assert_eq!(results, [Ok(1), Err(EvalError::NoOperands)]);
+        }
+    }
+
+    #[test]
+    fn transposed_worksheets_are_equivalent() {
+        let worksheet = Worksheet::new(EXAMPLE);
+        for reading in [Reading::Rows, Reading::Columns] {
+            let transposed = worksheet.transposed(reading);
+            assert_eq!(
+                transposed.grand_total(reading.other()),
+                worksheet.grand_total(reading),
+                "{reading:?}\n{transposed}"
+            );
+            assert_eq!(
+                all_operands(&transposed, reading.other()),
+                all_operands(&worksheet, reading)
+            );
+            let back = transposed.transposed(reading.other());
+            assert_eq!(
+                all_operands(&back, reading),
+                all_operands(&worksheet, reading)
+            );
+        }
+    }
 }
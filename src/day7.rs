@@ -1,5 +1,467 @@
 use aoc_runner_derive::aoc;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    fn offset(self) -> (isize, isize) {
+        match self {
+            Direction::Up => (-1, 0),
+            Direction::Down => (1, 0),
+            Direction::Left => (0, -1),
+            Direction::Right => (0, 1),
+        }
+    }
+
+    /// The direction on the left of a beam travelling this way.
+    fn turn_left(self) -> Self {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Left => Direction::Down,
+            Direction::Down => Direction::Right,
+            Direction::Right => Direction::Up,
+        }
+    }
+
+    fn turn_right(self) -> Self {
+        self.turn_left().turn_left().turn_left()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tile {
+    Empty,
+    /// Where the beam enters the manifold, heading down.
+    Start,
+    Splitter,
+    /// `/`, which turns a beam heading right to head up.
+    MirrorUp,
+    /// `\`, which turns a beam heading right to head down.
+    MirrorDown,
+    /// `#`, which stops any beam.
+    Absorber,
+}
+
+impl Tile {
+    fn parse(byte: u8) -> Self {
+        match byte {
+            b'.' => Tile::Empty,
+            b'S' => Tile::Start,
+            b'^' => Tile::Splitter,
+            b'/' => Tile::MirrorUp,
+            b'\\' => Tile::MirrorDown,
+            b'#' => Tile::Absorber,
+            tile => panic!("invalid tile: {}", tile as char),
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Tile::Empty => '.',
+            Tile::Start => 'S',
+            Tile::Splitter => '^',
+            Tile::MirrorUp => '/',
+            Tile::MirrorDown => '\\',
+            Tile::Absorber => '#',
+        }
+    }
+}
+
+/// Where a splitter sends a beam that hits it. The side outputs leave from
+/// the cells beside the splitter, on the beam's left and right, carrying on
+/// in the beam's direction; `through` carries on past the splitter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Splitter {
+    pub left: bool,
+    pub through: bool,
+    pub right: bool,
+}
+
+/// The splitters of the puzzle, which send beams out either side.
+impl Default for Splitter {
+    fn default() -> Self {
+        Splitter {
+            left: true,
+            through: false,
+            right: true,
+        }
+    }
+}
+
+/// A beam in a cell, travelling in a direction, found to lead back to
+/// itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeamCycle {
+    pub row: usize,
+    pub col: usize,
+    pub direction: Direction,
+}
+
+/// A tachyon manifold of tiles that beams can cross in any direction.
+#[derive(Debug, Clone)]
+pub struct Manifold {
+    row_len: usize,
+    tiles: Vec<Tile>,
+    start: usize,
+    splitter: Splitter,
+}
+
+// Where a beam goes next after interacting with a tile.
+enum Next {
+    /// Off the edge of the manifold, leaving the cell at `pos` in a direction.
+    Exit(usize, Direction),
+    /// Into a cell, travelling in a direction.
+    Beam(usize, Direction),
+}
+
+impl Manifold {
+    pub fn new(input: &str) -> Self {
+        Self::with_splitter(input, Splitter::default())
+    }
+
+    pub fn with_splitter(input: &str, splitter: Splitter) -> Self {
+        let row_len = input.lines().next().unwrap().len();
+        let mut tiles = Vec::new();
+        for line in input.lines() {
+            assert_eq!(line.len(), row_len, "rows must all be the same length");
+            tiles.extend(line.bytes().map(Tile::parse));
+        }
+        let start = tiles.iter().position(|&tile| tile == Tile::Start).unwrap();
+        Manifold {
+            row_len,
+            tiles,
+            start,
+            splitter,
+        }
+    }
+
+    // Index of a beam state in per-state tables.
+    fn state(pos: usize, direction: Direction) -> usize {
+        pos * 4 + direction as usize
+    }
+
+    fn step(&self, pos: usize, direction: Direction) -> Next {
+        let (dy, dx) = direction.offset();
+        let row = (pos / self.row_len).checked_add_signed(dy);
+        let col = (pos % self.row_len).checked_add_signed(dx);
+        match (row, col) {
+            (Some(row), Some(col))
+                if col < self.row_len && row * self.row_len < self.tiles.len() =>
+            {
+                Next::Beam(row * self.row_len + col, direction)
+            }
+            _ => Next::Exit(pos, direction),
+        }
+    }
+
+    // Where a beam in the cell at `pos` travelling in `direction` goes next.
+    // A beam that's stopped goes nowhere.
+    fn next(&self, pos: usize, direction: Direction) -> Vec<Next> {
+        match self.tiles[pos] {
+            Tile::Empty | Tile::Start => vec![self.step(pos, direction)],
+            Tile::Absorber => Vec::new(),
+            Tile::MirrorUp => {
+                let turned = match direction {
+                    Direction::Right | Direction::Left => direction.turn_left(),
+                    Direction::Up | Direction::Down => direction.turn_right(),
+                };
+                vec![self.step(pos, turned)]
+            }
+            Tile::MirrorDown => {
+                let turned = match direction {
+                    Direction::Right | Direction::Left => direction.turn_right(),
+                    Direction::Up | Direction::Down => direction.turn_left(),
+                };
+                vec![self.step(pos, turned)]
+            }
+            Tile::Splitter => {
+                let mut next = Vec::new();
+                // The side beams start in the cells beside the splitter.
+                let side = |turned: Direction| match self.step(pos, turned) {
+                    Next::Beam(side_pos, _) => Next::Beam(side_pos, direction),
+                    exit => exit,
+                };
+                if self.splitter.left {
+                    next.push(side(direction.turn_left()));
+                }
+                if self.splitter.through {
+                    next.push(self.step(pos, direction));
+                }
+                if self.splitter.right {
+                    next.push(side(direction.turn_right()));
+                }
+                next
+            }
+        }
+    }
+
+    // Which beam states any beam reaches.
+    fn reachable(&self) -> Vec<bool> {
+        let mut seen = vec![false; self.tiles.len() * 4];
+        let mut beams = vec![(self.start, Direction::Down)];
+        seen[Self::state(self.start, Direction::Down)] = true;
+        while let Some((pos, direction)) = beams.pop() {
+            for next in self.next(pos, direction) {
+                if let Next::Beam(next_pos, next_direction) = next {
+                    let state = Self::state(next_pos, next_direction);
+                    if !seen[state] {
+                        seen[state] = true;
+                        beams.push((next_pos, next_direction));
+                    }
+                }
+            }
+        }
+        seen
+    }
+
+    /// The number of splitters any beam hits, from any direction.
+    pub fn count_splits(&self) -> usize {
+        let seen = self.reachable();
+        (0..self.tiles.len())
+            .filter(|&pos| {
+                self.tiles[pos] == Tile::Splitter && seen[pos * 4..][..4].contains(&true)
+            })
+            .count()
+    }
+
+    // The reachable beams, each before any beam that follows from it.
+    fn topological_order(&self) -> Result<Vec<(usize, Direction)>, BeamCycle> {
+        let mut done = vec![false; self.tiles.len() * 4];
+        let mut on_path = vec![false; self.tiles.len() * 4];
+        let mut order = Vec::new();
+        // Each beam is visited once to push what comes after it, and again to
+        // place it once all of those are placed.
+        let mut stack = vec![(self.start, Direction::Down, false)];
+        while let Some((pos, direction, expanded)) = stack.pop() {
+            let state = Self::state(pos, direction);
+            if expanded {
+                done[state] = true;
+                on_path[state] = false;
+                order.push((pos, direction));
+                continue;
+            }
+            if done[state] {
+                continue;
+            }
+            on_path[state] = true;
+            stack.push((pos, direction, true));
+            for next in self.next(pos, direction) {
+                if let Next::Beam(next_pos, next_direction) = next {
+                    let next_state = Self::state(next_pos, next_direction);
+                    if on_path[next_state] {
+                        return Err(BeamCycle {
+                            row: next_pos / self.row_len,
+                            col: next_pos % self.row_len,
+                            direction: next_direction,
+                        });
+                    }
+                    if !done[next_state] {
+                        stack.push((next_pos, next_direction, false));
+                    }
+                }
+            }
+        }
+        order.reverse();
+        Ok(order)
+    }
+
+    // Follow the timelines of a single particle forward, calling `end` with
+    // how each beam ends (`None` if it's stopped) and the number of timelines
+    // that end that way.
+    fn follow_timelines(&self, mut end: impl FnMut(Option<Next>, u128)) -> Result<(), BeamCycle> {
+        let mut timelines = vec![0u128; self.tiles.len() * 4];
+        timelines[Self::state(self.start, Direction::Down)] = 1;
+        for (pos, direction) in self.topological_order()? {
+            let count = timelines[Self::state(pos, direction)];
+            let next = self.next(pos, direction);
+            if next.is_empty() {
+                end(None, count);
+            }
+            for next in next {
+                match next {
+                    Next::Beam(next_pos, next_direction) => {
+                        let next_count = &mut timelines[Self::state(next_pos, next_direction)];
+                        *next_count = next_count
+                            .checked_add(count)
+                            .expect("timeline count overflows u128");
+                    }
+                    exit => end(Some(exit), count),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of timelines a single particle can end up in, where each
+    /// splitter sends it down just one of its outputs and a timeline ends when
+    /// the particle leaves the manifold or is stopped.
+    ///
+    /// A particle that can get back to a cell it's passed through, travelling
+    /// the same way, has either infinitely many timelines or one that never
+    /// ends, so that's reported as the first such beam found.
+    pub fn count_timelines(&self) -> Result<u128, BeamCycle> {
+        let mut total = 0u128;
+        self.follow_timelines(|_, count| {
+            total = total
+                .checked_add(count)
+                .expect("timeline count overflows u128");
+        })?;
+        Ok(total)
+    }
+
+    /// The number of distinct beams leaving through the bottom of each
+    /// column.
+    pub fn bottom_exits(&self) -> Vec<usize> {
+        self.exits_down(&self.reachable())
+    }
+
+    // Count the beams in `seen` that leave the bottom row heading down. A
+    // beam can turn down off a mirror or splitter in the bottom row, so two
+    // beams can leave the same column.
+    fn exits_down(&self, seen: &[bool]) -> Vec<usize> {
+        let mut exits = vec![0; self.row_len];
+        let bottom_row = self.tiles.len() - self.row_len;
+        for pos in bottom_row..self.tiles.len() {
+            for direction in [
+                Direction::Up,
+                Direction::Down,
+                Direction::Left,
+                Direction::Right,
+            ] {
+                if !seen[Self::state(pos, direction)] {
+                    continue;
+                }
+                for next in self.next(pos, direction) {
+                    if let Next::Exit(exit_pos, Direction::Down) = next {
+                        exits[exit_pos % self.row_len] += 1;
+                    }
+                }
+            }
+        }
+        exits
+    }
+
+    /// The number of timelines in which the particle leaves through the
+    /// bottom of each column.
+    pub fn bottom_timelines(&self) -> Result<Vec<u128>, BeamCycle> {
+        let mut timelines = vec![0u128; self.row_len];
+        let bottom_row = self.tiles.len() - self.row_len;
+        self.follow_timelines(|how, count| {
+            if let Some(Next::Exit(pos, Direction::Down)) = how
+                && pos >= bottom_row
+            {
+                let column = &mut timelines[pos % self.row_len];
+                *column = column
+                    .checked_add(count)
+                    .expect("timeline count overflows u128");
+            }
+        })?;
+        Ok(timelines)
+    }
+
+    /// Which splitters the beams hit and where they leave the manifold.
+    pub fn splitter_report(&self) -> SplitterReport {
+        let seen = self.reachable();
+        let reached = |pos: usize| seen[pos * 4..][..4].contains(&true);
+        let coords = |pos: usize| (pos / self.row_len, pos % self.row_len);
+        let splitters = (0..self.tiles.len()).filter(|&pos| self.tiles[pos] == Tile::Splitter);
+        let (hit, unreachable): (Vec<usize>, Vec<usize>) = splitters.partition(|&pos| reached(pos));
+
+        let annotated = self
+            .tiles
+            .chunks(self.row_len)
+            .enumerate()
+            .map(|(row, tiles)| {
+                tiles
+                    .iter()
+                    .enumerate()
+                    .map(|(col, &tile)| {
+                        let pos = row * self.row_len + col;
+                        let beam = |direction| seen[Self::state(pos, direction)];
+                        let vertical = beam(Direction::Up) || beam(Direction::Down);
+                        let horizontal = beam(Direction::Left) || beam(Direction::Right);
+                        match tile {
+                            Tile::Splitter if !reached(pos) => 'x',
+                            Tile::Empty => match (vertical, horizontal) {
+                                (false, false) => '.',
+                                (true, false) => '|',
+                                (false, true) => '-',
+                                (true, true) => '+',
+                            },
+                            tile => tile.symbol(),
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        SplitterReport {
+            hit: hit.into_iter().map(coords).collect(),
+            unreachable: unreachable.into_iter().map(coords).collect(),
+            bottom_exits: self.exits_down(&seen),
+            bottom_timelines: self.bottom_timelines(),
+            annotated,
+        }
+    }
+}
+
+/// The splitters of a manifold that beams do and don't reach, and the beams
+/// and timelines leaving the bottom of each column.
+///
+/// Displays as a copy of the manifold with beams drawn over empty cells as
+/// `|`, `-` or `+` where they cross, and unreached splitters as `x`, followed
+/// by the beams and timelines leaving each column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitterReport {
+    /// The `(row, col)` of each splitter a beam hits, in reading order.
+    pub hit: Vec<(usize, usize)>,
+    /// The `(row, col)` of each splitter no beam reaches, in reading order.
+    pub unreachable: Vec<(usize, usize)>,
+    /// The number of distinct beams leaving the bottom of each column.
+    pub bottom_exits: Vec<usize>,
+    /// The number of timelines leaving the bottom of each column, as in
+    /// [`Manifold::bottom_timelines`].
+    pub bottom_timelines: Result<Vec<u128>, BeamCycle>,
+    annotated: Vec<String>,
+}
+
+impl std::fmt::Display for SplitterReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.annotated {
+            writeln!(f, "{line}")?;
+        }
+        writeln!(
+            f,
+            "{} splitters hit, {} unreachable",
+            self.hit.len(),
+            self.unreachable.len()
+        )?;
+        for (col, &beams) in self.bottom_exits.iter().enumerate() {
+            if beams == 0 {
+                continue;
+            }
+            write!(f, "column {col}: {beams} beams")?;
+            if let Ok(timelines) = &self.bottom_timelines {
+                write!(f, ", {} timelines", timelines[col])?;
+            }
+            writeln!(f)?;
+        }
+        if let Err(cycle) = &self.bottom_timelines {
+            writeln!(
+                f,
+                "beam heading {:?} at row {}, column {} loops back on itself",
+                cycle.direction, cycle.row, cycle.col
+            )?;
+        }
+        Ok(())
+    }
+}
+
 #[aoc(day7, part1)]
 fn part1(input: &str) -> usize {
     let (first_line, remaining_input) = input.split_once('\n').unwrap();
@@ -49,3 +511,52 @@ fn part2(input: &str) -> usize {
     }
     beams.iter().sum()
 }
+
+#[aoc(day7, part1, tiles)]
+fn part1_tiles(input: &str) -> usize {
+    Manifold::new(input).count_splits()
+}
+
+#[aoc(day7, part2, tiles)]
+fn part2_tiles(input: &str) -> u128 {
+    Manifold::new(input).count_timelines().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_beams_exit_once() {
+        let manifold = Manifold::new("..S..\n.....\n..^..\n.....\n.^.^.\n.....");
+        assert_eq!(manifold.bottom_exits(), [1, 0, 1, 0, 1]);
+        assert_eq!(manifold.bottom_timelines(), Ok(vec![1, 0, 2, 0, 1]));
+        let report = manifold.splitter_report();
+        assert_eq!(report.bottom_exits, [1, 0, 1, 0, 1]);
+        assert!(
+            report
+                .to_string()
+                .contains("column 2: 1 beams, 2 timelines")
+        );
+    }
+
+    #[test]
+    fn splitter_in_bottom_row_sends_two_beams_down() {
+        // Beams reach the splitter from both sides, and each sends a beam
+        // straight down out of the splitter's cell.
+        let manifold = Manifold::new("..S..\n..^..\n.\\^/.");
+        assert_eq!(manifold.bottom_exits(), [0, 0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn exits_are_counted_despite_a_cycle() {
+        // The splitter sends one beam round the loop back to the start and
+        // the other off the mirror below it and out of the bottom.
+        let manifold = Manifold::new("./.\\.\n.S...\n.../.\n.\\^..\n...\\.");
+        assert!(manifold.bottom_timelines().is_err());
+        let report = manifold.splitter_report();
+        assert!(report.bottom_timelines.is_err());
+        assert_eq!(report.bottom_exits, [0, 0, 0, 1, 0]);
+        assert!(report.to_string().contains("column 3: 1 beams\n"));
+    }
+}
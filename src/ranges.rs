@@ -0,0 +1,20 @@
+use std::ops::Range;
+
+/// Sort `ranges` by start and merge any that overlap or touch, dropping empty
+/// ones, so the result is a sorted list of disjoint ranges with a gap between
+/// each.
+pub fn normalize<T: Ord + Copy>(ranges: &mut Vec<Range<T>>) {
+    ranges.retain(|r| r.start < r.end);
+    ranges.sort_by_key(|r| r.start);
+
+    let mut merged_len = 0;
+    for idx in 0..ranges.len() {
+        if merged_len > 0 && ranges[idx].start <= ranges[merged_len - 1].end {
+            ranges[merged_len - 1].end = std::cmp::max(ranges[merged_len - 1].end, ranges[idx].end);
+        } else {
+            ranges[merged_len] = ranges[idx].clone();
+            merged_len += 1;
+        }
+    }
+    ranges.truncate(merged_len);
+}